@@ -1,13 +1,12 @@
 import { Program, web3 } from '@project-serum/anchor';
 import * as anchor from '@project-serum/anchor';
-import { Keypair, PublicKey, SystemProgram, SYSVAR_RENT_PUBKEY, PartiallyDecodedInstruction, Transaction, } from '@solana/web3.js';
+import { Keypair, PublicKey, SystemProgram, SYSVAR_RENT_PUBKEY, Transaction, } from '@solana/web3.js';
 import fs from 'fs';
 import NodeWallet from '@project-serum/anchor/dist/cjs/nodewallet';
 import { ClimableInfo, GameData, GlobalPool, PlayerPool } from './types';
 import { IDL as GameIDL } from "../target/types/dice";
-import { bs58 } from '@project-serum/anchor/dist/cjs/utils/bytes';
 import { findProgramAddressSync } from '@project-serum/anchor/dist/cjs/utils/pubkey';
-import { GLOBAL_AUTHORITY_SEED, LAMPORTS, NONCE, PLAYER_POOL_SEED, TOKEN_ADDR, USER_POOL_SIZE, VAULT_AUTHORITY_SEED, getAssociatedTokenAccount, network, programId } from './config';
+import { GLOBAL_AUTHORITY_SEED, LAMPORTS, PLAYER_POOL_SEED, TOKEN_ADDR, USER_POOL_SIZE, VAULT_AUTHORITY_SEED, getAssociatedTokenAccount, network, programId } from './config';
 import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
 
 // Set the initial program and provider
@@ -476,13 +475,13 @@ export const getAllTransactions = async (programId: PublicKey) => {
     return result;
 }
 
-// Parse activity from a transaction siganture
+// Parse the program's Anchor events (BetPlaced, BetSettled, VaultWithdrawn, ...) from a transaction signature
 export const getDataFromSignature = async (sig: string) => {
 
     // Get transaction data from on-chain
     let tx;
     try {
-        tx = await solConnection.getParsedTransaction(sig, "confirmed");
+        tx = await solConnection.getTransaction(sig, { commitment: "confirmed", maxSupportedTransactionVersion: 0 });
     }
     catch (e) {
     }
@@ -497,49 +496,15 @@ export const getDataFromSignature = async (sig: string) => {
         return;
     }
 
-    // Parse activty by analyze fetched Transaction data
-    let length = tx.transaction.message.instructions.length;
-    let valid = 0;
-    let hash = "";
-    let ixId = -1;
-    for (let i = 0; i < length; i++) {
-        hash = (tx.transaction.message.instructions[i] as PartiallyDecodedInstruction).data;
-        if (hash !== undefined && hash.slice(0, 8) === NONCE) {
-            valid = 1;
-        }
-        if (valid === 1) {
-            ixId = i;
-            break;
-        }
-    }
-
-    if (ixId === -1 || valid === 0) {
-        return;
-    }
-
-    let ts = tx.slot ?? 0;
-    if (!tx.meta.innerInstructions) {
-        console.log(`Can't parse innerInstructions ${sig}`);
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    const events = [...parser.parseLogs(tx.meta.logMessages ?? [])];
+    if (events.length === 0) {
         return;
     }
 
-
-    let accountKeys = (tx.transaction.message.instructions[ixId] as PartiallyDecodedInstruction).accounts;
-    let signer = accountKeys[0].toBase58();
-
-    let bytes = bs58.decode(hash);
-    let a = bytes.slice(8, 16).reverse();
-    let type = new anchor.BN(a).toNumber();
-    let b = bytes.slice(16, 24).reverse();
-    let sol_price = new anchor.BN(b).toNumber();
-
-    let state = type === ts % 2 ? 1 : 0;
-
-    let result = {
-        type: type, address: signer, bet_amount: sol_price, block_hash: ts, win: state, signature: sig,
+    return {
+        slot: tx.slot, signature: sig, events: events.map((event) => ({ name: event.name, data: event.data })),
     };
-
-    return result;
 };
 
 export const resizeAllUserPool = async () => {
@@ -1,7 +1,17 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::slot_hashes;
+use anchor_spl::token::{Mint, Token, TokenAccount};
 use solana_program::pubkey::Pubkey;
 
-use crate::constants::{GLOBAL_AUTHORITY_SEED, PLAYER_POOL_SEED, VAULT_AUTHORITY_SEED};
+use crate::constants::{
+    ALLOWED_RECIPIENT_SEED, AUTO_BET_SEED, BPS_DENOMINATOR, CONFIG_FIELD_COUNT,
+    GLOBAL_AUTHORITY_SEED, HOUSE_SHARES_SEED, LP_DECIMALS, LP_ESCROW_SEED, LP_MINT_SEED,
+    LP_WITHDRAWAL_SEED, MAX_ROLLS, MAX_TREASURY_SIGNERS, MIN_CONFIG_TIMELOCK, PLAYER_BALANCE_SEED, PLAYER_POOL_SEED, ROLL_RANGE, SESSION_TOKEN_SEED,
+    TOKEN_CONFIG_SEED, TREASURY_CONFIG_SEED, VAULT_AUTHORITY_SEED, VRF_RANDOMNESS_SEED,
+    WITHDRAWAL_PROPOSAL_SEED,
+};
+use crate::error::GameError;
+use crate::utils::{compute_roll_at, hash_server_seed, mix_slot_hash, win_outcomes};
 
 #[account]
 #[derive(Default)]
@@ -15,10 +25,207 @@ pub struct GlobalPool {
     pub min_bet_amount: u64,         // 8
     pub min_num: u8,                // 1
     pub max_num: u8,                // 1
+    pub bet_timeout: i64,            // 8, seconds before an unsettled bet can be refunded
+    pub outstanding_liability: u64,  // 8, net winnings owed if every open SOL bet wins
+    pub max_liability_bps: u64,      // 8, cap on outstanding_liability as a share of the casino vault
+    pub dynamic_max_win_bps: u64,    // 8, 0 uses the static max_win_amount
+    pub guardian: Pubkey,            // 32, may pause alongside super_admin
+    pub pause_flags: u8,             // 1, PAUSE_* bits
+    pub pending_super_admin: Pubkey,         // 32
+    pub pending_operation_authority: Pubkey, // 32
+    pub pending_finance_authority: Pubkey,   // 32
+    pub pending_update_authority: Pubkey,    // 32
+    pub config_timelock: i64,                // 8, notice period before queued config applies
+    pub pending_configs: [PendingConfig; CONFIG_FIELD_COUNT], // 16 * 8, indexed by ConfigField
+    pub randomness_mode: RandomnessMode,     // 1, source of the roll for new SOL bets
+    pub vrf_program: Pubkey,                 // 32, owner of randomness accounts in Vrf mode
+    pub slot_hash_max_bet: u64,              // 8, largest bet settled from slot hashes, 0 disables
+    pub withdrawal_epoch_cap: u64,           // 8, most SOL withdrawn from the casino vault per epoch
+    pub withdrawal_epoch: u64,               // 8, epoch withdrawn_this_epoch counts toward
+    pub withdrawn_this_epoch: u64,           // 8
+    pub min_reserve: u64,                    // 8, kept in the casino vault on top of outstanding_liability
+    pub lp_mint: Pubkey,                     // 32, default until init_liquidity_pool
+    pub lp_epoch_duration: i64,              // 8, seconds per LP withdrawal epoch
+    pub pending_randomness_mode: PendingRandomnessMode, // 41, queued by set_randomness_mode
 }
 
 impl GlobalPool {
-    pub const DATA_SIZE: usize = 32 + 32 + 32 + 32 + 8 + 8 + 8 + 1 + 1; //  154
+    pub const DATA_SIZE: usize = 32 + 32 + 32 + 32 + 8 + 8 + 8 + 1 + 1 + 8 + 8 + 8 + 8 + 32 + 1
+        + 32 * 4
+        + 8
+        + PendingConfig::DATA_SIZE * CONFIG_FIELD_COUNT
+        + 1
+        + 32
+        + 8
+        + 8 * 3
+        + 8
+        + 32
+        + 8
+        + PendingRandomnessMode::DATA_SIZE; //  717
+
+    // Layout deployed before bet timeouts, exposure limits and the config timelock were added
+    pub const LEGACY_DATA_SIZE: usize = 32 + 32 + 32 + 32 + 8 + 8 + 8 + 1 + 1; //  154
+
+    pub fn check_config(&self, field: ConfigField, value: u64) -> Result<()> {
+        match field {
+            ConfigField::Rtp => {
+                require!(value < 100, GameError::InvalidRtp);
+            }
+            ConfigField::MinNum => {
+                require!(value <= self.max_num as u64, GameError::InvalidTargetNumber);
+            }
+            ConfigField::MaxNum => {
+                require!(
+                    value >= self.min_num as u64 && value <= u8::MAX as u64,
+                    GameError::InvalidTargetNumber
+                );
+            }
+            ConfigField::BetTimeout => {
+                require!(
+                    value > 0 && value <= i64::MAX as u64,
+                    GameError::InvalidBetTimeout
+                );
+            }
+            ConfigField::LpEpochDuration => {
+                require!(
+                    value > 0 && value <= i64::MAX as u64,
+                    GameError::InvalidLpEpochDuration
+                );
+            }
+            ConfigField::ConfigTimelock => {
+                require!(
+                    value >= MIN_CONFIG_TIMELOCK as u64 && value <= i64::MAX as u64,
+                    GameError::InvalidConfigTimelock
+                );
+            }
+            ConfigField::MaxLiabilityBps | ConfigField::DynamicMaxWinBps => {
+                require!(value <= BPS_DENOMINATOR, GameError::InvalidBps);
+            }
+            ConfigField::MaxWinAmount
+            | ConfigField::MinBetAmount
+            | ConfigField::SlotHashMaxBet
+            | ConfigField::WithdrawalEpochCap
+            | ConfigField::MinReserve => {}
+        }
+
+        Ok(())
+    }
+
+    // Validate and write a config value, returning the previous one
+    pub fn apply_config(&mut self, field: ConfigField, value: u64) -> Result<u64> {
+        self.check_config(field, value)?;
+
+        let old_value = match field {
+            ConfigField::Rtp => std::mem::replace(&mut self.rtp, value),
+            ConfigField::MaxWinAmount => std::mem::replace(&mut self.max_win_amount, value),
+            ConfigField::MinBetAmount => std::mem::replace(&mut self.min_bet_amount, value),
+            ConfigField::MinNum => std::mem::replace(&mut self.min_num, value as u8) as u64,
+            ConfigField::MaxNum => std::mem::replace(&mut self.max_num, value as u8) as u64,
+            ConfigField::BetTimeout => {
+                std::mem::replace(&mut self.bet_timeout, value as i64) as u64
+            }
+            ConfigField::MaxLiabilityBps => std::mem::replace(&mut self.max_liability_bps, value),
+            ConfigField::DynamicMaxWinBps => {
+                std::mem::replace(&mut self.dynamic_max_win_bps, value)
+            }
+            ConfigField::SlotHashMaxBet => std::mem::replace(&mut self.slot_hash_max_bet, value),
+            ConfigField::WithdrawalEpochCap => {
+                std::mem::replace(&mut self.withdrawal_epoch_cap, value)
+            }
+            ConfigField::MinReserve => std::mem::replace(&mut self.min_reserve, value),
+            ConfigField::LpEpochDuration => {
+                std::mem::replace(&mut self.lp_epoch_duration, value as i64) as u64
+            }
+            ConfigField::ConfigTimelock => {
+                std::mem::replace(&mut self.config_timelock, value as i64) as u64
+            }
+        };
+
+        Ok(old_value)
+    }
+
+    // Count a casino vault withdrawal against the cap of the current epoch
+    pub fn record_withdrawal(&mut self, amount: u64, epoch: u64) -> Result<()> {
+        if epoch != self.withdrawal_epoch {
+            self.withdrawal_epoch = epoch;
+            self.withdrawn_this_epoch = 0;
+        }
+
+        let withdrawn = self
+            .withdrawn_this_epoch
+            .checked_add(amount)
+            .ok_or(GameError::MathOverflow)?;
+        require!(
+            withdrawn <= self.withdrawal_epoch_cap,
+            GameError::WithdrawalCapExceeded
+        );
+        self.withdrawn_this_epoch = withdrawn;
+
+        Ok(())
+    }
+
+    // Lowest the casino vault may be withdrawn to while still covering every open bet
+    pub fn reserve_floor(&self) -> Result<u64> {
+        self.min_reserve
+            .checked_add(self.outstanding_liability)
+            .ok_or_else(|| error!(GameError::MathOverflow))
+    }
+
+    // Start of the LP epoch after the one now falls in
+    pub fn next_lp_epoch(&self, now: i64) -> Result<i64> {
+        (now / self.lp_epoch_duration + 1)
+            .checked_mul(self.lp_epoch_duration)
+            .ok_or_else(|| error!(GameError::MathOverflow))
+    }
+
+    // When a change queued now may apply
+    pub fn config_effective_at(&self, now: i64) -> Result<i64> {
+        now.checked_add(self.config_timelock)
+            .ok_or_else(|| error!(GameError::MathOverflow))
+    }
+
+    pub fn is_paused(&self, flag: u8) -> bool {
+        self.pause_flags & flag != 0
+    }
+
+    // Max net win for a bet: a share of the vault when dynamic mode is on, otherwise the static limit
+    pub fn effective_max_win(&self, static_max_win: u64, vault_balance: u64) -> Result<u64> {
+        if self.dynamic_max_win_bps == 0 {
+            return Ok(static_max_win);
+        }
+
+        let max_win = (vault_balance as u128)
+            .checked_mul(self.dynamic_max_win_bps as u128)
+            .ok_or(GameError::MathOverflow)?
+            / BPS_DENOMINATOR as u128;
+
+        u64::try_from(max_win).map_err(|_| error!(GameError::MathOverflow))
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct TokenConfig {
+    pub mint: Pubkey,               // 32
+    pub min_bet_amount: u64,        // 8
+    pub max_win_amount: u64,        // 8
+    pub outstanding_liability: u64, // 8
+    pub pending: PendingTokenConfig, // 24, queued by set_token_config
+}
+
+impl TokenConfig {
+    pub const DATA_SIZE: usize = 32 + 8 + 8 + 8 + PendingTokenConfig::DATA_SIZE; // 80
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Default, Clone, Copy)]
+pub struct PendingTokenConfig {
+    pub min_bet_amount: u64, // 8
+    pub max_win_amount: u64, // 8
+    pub effective_at: i64,   // 8, 0 when nothing is queued
+}
+
+impl PendingTokenConfig {
+    pub const DATA_SIZE: usize = 8 + 8 + 8; // 24
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Default, Clone, PartialEq)]
@@ -29,6 +236,172 @@ pub enum GameStatus {
     Lose,       // 1
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Default, Clone, Copy)]
+pub struct PendingConfig {
+    pub value: u64,        // 8
+    pub effective_at: i64, // 8, 0 when nothing is queued
+}
+
+impl PendingConfig {
+    pub const DATA_SIZE: usize = 8 + 8; // 16
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Default, Clone, Copy)]
+pub struct PendingRandomnessMode {
+    pub randomness_mode: RandomnessMode, // 1
+    pub vrf_program: Pubkey,             // 32
+    pub effective_at: i64,               // 8, 0 when nothing is queued
+}
+
+impl PendingRandomnessMode {
+    pub const DATA_SIZE: usize = 1 + 32 + 8; // 41
+}
+
+// Variant order indexes GlobalPool::pending_configs, keep CONFIG_FIELD_COUNT in sync
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConfigField {
+    Rtp,
+    MaxWinAmount,
+    MinBetAmount,
+    MinNum,
+    MaxNum,
+    BetTimeout,
+    MaxLiabilityBps,
+    DynamicMaxWinBps,
+    SlotHashMaxBet,
+    WithdrawalEpochCap,
+    MinReserve,
+    LpEpochDuration,
+    ConfigTimelock,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameKind {
+    #[default]
+    OverUnder, // roll under or over target_num, picked by is_under
+    CoinFlip,  // roll parity matches target_num, 0 or 1
+    Range,     // roll within target_num..=range_end
+    Exact,     // roll equals target_num
+}
+
+impl GameKind {
+    // Number of rolls out of ROLL_RANGE that win this bet, rejecting malformed bets.
+    // Every game pays rtp / (win_outcomes / ROLL_RANGE) through calc_multiplier.
+    pub fn win_outcomes(self, target_num: u8, range_end: u8, is_under: bool) -> Result<u64> {
+        let outcomes = match self {
+            GameKind::OverUnder => win_outcomes(target_num, is_under),
+            GameKind::CoinFlip => {
+                require!(target_num <= 1, GameError::InvalidTargetNumber);
+                ROLL_RANGE / 2
+            }
+            GameKind::Range => {
+                require!(
+                    target_num <= range_end && (range_end as u64) < ROLL_RANGE,
+                    GameError::InvalidTargetNumber
+                );
+                (range_end - target_num) as u64 + 1
+            }
+            GameKind::Exact => {
+                require!((target_num as u64) < ROLL_RANGE, GameError::InvalidTargetNumber);
+                1
+            }
+        };
+
+        // A bet covering every roll can only lose the house edge
+        require!(
+            outcomes > 0 && outcomes < ROLL_RANGE,
+            GameError::InvalidTargetNumber
+        );
+
+        Ok(outcomes)
+    }
+
+    pub fn is_win(self, roll: u8, target_num: u8, range_end: u8, is_under: bool) -> bool {
+        match self {
+            GameKind::OverUnder if is_under => roll < target_num,
+            GameKind::OverUnder => roll > target_num,
+            GameKind::CoinFlip => roll % 2 == target_num,
+            GameKind::Range => (target_num..=range_end).contains(&roll),
+            GameKind::Exact => roll == target_num,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RandomnessMode {
+    #[default]
+    CommitReveal, // operator reveals a pre-committed server seed
+    Vrf,          // roll comes from a VRF randomness account requested after the bet
+    SlotHash,     // roll comes from the first slot hash after the bet, small bets only
+}
+
+// Layout of an ORAO-style randomness request account, after the 8 byte discriminator
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct VrfRandomness {
+    pub seed: [u8; 32],
+    pub randomness: [u8; 64], // all zero until the request is fulfilled
+}
+
+impl VrfRandomness {
+    // Address of the request account the VRF program opens for a player pool
+    pub fn address(player_pool: &Pubkey, vrf_program: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[VRF_RANDOMNESS_SEED.as_bytes(), player_pool.as_ref()],
+            vrf_program,
+        )
+        .0
+    }
+
+    pub fn load(account: &AccountInfo, vrf_program: &Pubkey) -> Result<Self> {
+        let randomness = Self::read(account, vrf_program)?;
+
+        require!(
+            randomness.is_fulfilled(),
+            GameError::RandomnessNotFulfilled
+        );
+
+        Ok(randomness)
+    }
+
+    // Whether the request account holds output, false while the VRF program has not opened it
+    pub fn is_fulfilled_at(account: &AccountInfo, vrf_program: &Pubkey) -> Result<bool> {
+        if account.data_is_empty() {
+            return Ok(false);
+        }
+
+        Ok(Self::read(account, vrf_program)?.is_fulfilled())
+    }
+
+    fn is_fulfilled(&self) -> bool {
+        self.randomness != [0u8; 64]
+    }
+
+    fn read(account: &AccountInfo, vrf_program: &Pubkey) -> Result<Self> {
+        require!(
+            account.owner == vrf_program,
+            GameError::InvalidRandomnessAccount
+        );
+
+        let data = account.try_borrow_data()?;
+        let discriminator = anchor_lang::solana_program::hash::hash(b"account:Randomness");
+        require!(
+            data.len() >= 8 && data[..8] == discriminator.to_bytes()[..8],
+            GameError::InvalidRandomnessAccount
+        );
+
+        Self::deserialize(&mut &data[8..]).map_err(|_| error!(GameError::InvalidRandomnessAccount))
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AuthorityRole {
+    SuperAdmin,
+    Operation,
+    Finance,
+    Update,
+    Guardian,
+}
+
 #[derive(Accounts)]
 pub struct Initialize<'info> {
     #[account(
@@ -57,10 +430,15 @@ pub struct Initialize<'info> {
 
 #[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
 pub struct InitPlayGameParams {
+    pub game_kind: GameKind,
     pub target_number: u8,
-    pub is_under: bool,
+    pub range_end: u8, // Range only, last winning roll
+    pub is_under: bool, // OverUnder only
     pub bet_amount: u64,
-    pub game_session_id: u64
+    pub game_session_id: u64,
+    pub server_seed_hash: [u8; 32],
+    pub client_seed: [u8; 32],
+    pub slot_hash: bool, // settle from slot hashes instead of the server seed, SOL only
 }
 
 #[derive(Accounts)]
@@ -109,14 +487,17 @@ pub struct PlayGame<'info> {
     /// CHECK: This is not dangerous because we don't read or write from this account
     pub game_vault: AccountInfo<'info>,
 
+    /// CHECK: Vrf mode only. Must be the not yet requested VrfRandomness address for this player pool
+    pub vrf_randomness: Option<UncheckedAccount<'info>>,
+
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
 pub struct SetResultParams {
-    is_win: bool,
-    game_session_id: u64
+    pub game_session_id: u64,
+    pub server_seed: [u8; 32],
 }
 
 #[derive(Accounts)]
@@ -144,7 +525,8 @@ pub struct SetResult<'info> {
     #[account(
         mut,
         seeds = [&owner.key().as_ref(), PLAYER_POOL_SEED.as_bytes(), &params.game_session_id.to_be_bytes()[..]],
-        bump
+        bump,
+        close = operator
     )]
     pub player_pool: Account<'info, PlayerPool>,
 
@@ -164,20 +546,35 @@ pub struct SetResult<'info> {
     /// CHECK: This is not dangerous because we don't read or write from this account
     pub casino_vault: AccountInfo<'info>,
 
+    #[account(
+        mut,
+        seeds = [PLAYER_BALANCE_SEED.as_bytes(), owner.key().as_ref()],
+        bump,
+    )]
+    pub player_balance: Option<Account<'info, PlayerBalance>>,
+
+    #[account(address = slot_hashes::ID)]
+    /// CHECK: Read with find_slot_hash, the sysvar is too large to deserialize whole
+    pub slot_hashes: AccountInfo<'info>,
+
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct Withdraw<'info> {
+#[instruction(
+    game_session_id: u64
+)]
+pub struct SetResultVrf<'info> {
     #[account(
         mut,
-        address = global_authority.finance_authority
+        address = global_authority.operation_authority
     )]
-    pub financial_admin: Signer<'info>,
+    /// CHECK: Receives the player pool rent it paid in play_game
+    pub operator: AccountInfo<'info>,
 
     /// CHECK:
     #[account(mut)]
-    pub recipient: AccountInfo<'info>,
+    pub owner: AccountInfo<'info>,
 
     #[account(
         mut,
@@ -186,6 +583,22 @@ pub struct Withdraw<'info> {
     )]
     pub global_authority: Box<Account<'info, GlobalPool>>,
 
+    #[account(
+        mut,
+        seeds = [&owner.key().as_ref(), PLAYER_POOL_SEED.as_bytes(), &game_session_id.to_be_bytes()[..]],
+        bump,
+        close = operator
+    )]
+    pub player_pool: Account<'info, PlayerPool>,
+
+    #[account(
+        mut,
+        seeds = [&owner.key().as_ref(), VAULT_AUTHORITY_SEED.as_bytes(), &game_session_id.to_be_bytes()[..]],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub game_vault: AccountInfo<'info>,
+
     #[account(
         mut,
         seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
@@ -194,37 +607,1569 @@ pub struct Withdraw<'info> {
     /// CHECK: This is not dangerous because we don't read or write from this account
     pub casino_vault: AccountInfo<'info>,
 
+    #[account(address = player_pool.vrf_randomness @ GameError::InvalidRandomnessAccount)]
+    /// CHECK: Owner, layout and fulfilment are checked in VrfRandomness::load
+    pub vrf_randomness: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [PLAYER_BALANCE_SEED.as_bytes(), owner.key().as_ref()],
+        bump,
+    )]
+    pub player_balance: Option<Account<'info, PlayerBalance>>,
+
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct SetGlobalPool<'info> {
-    #[account(address = global_pool.update_authority)]
-    pub admin: Signer<'info>, // ADMIN_C must be this signer
+#[instruction(
+    game_session_id: u64
+)]
+pub struct SetResultSlotHash<'info> {
+    #[account(
+        mut,
+        address = global_authority.operation_authority
+    )]
+    /// CHECK: Receives the player pool rent it paid in play_game
+    pub operator: AccountInfo<'info>,
 
+    /// CHECK:
     #[account(mut)]
-    pub global_pool: Account<'info, GlobalPool>,
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        mut,
+        seeds = [&owner.key().as_ref(), PLAYER_POOL_SEED.as_bytes(), &game_session_id.to_be_bytes()[..]],
+        bump,
+        close = operator
+    )]
+    pub player_pool: Account<'info, PlayerPool>,
+
+    #[account(
+        mut,
+        seeds = [&owner.key().as_ref(), VAULT_AUTHORITY_SEED.as_bytes(), &game_session_id.to_be_bytes()[..]],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub game_vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub casino_vault: AccountInfo<'info>,
+
+    #[account(address = slot_hashes::ID)]
+    /// CHECK: Read with find_slot_hash, the sysvar is too large to deserialize whole
+    pub slot_hashes: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [PLAYER_BALANCE_SEED.as_bytes(), owner.key().as_ref()],
+        bump,
+    )]
+    pub player_balance: Option<Account<'info, PlayerBalance>>,
+
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct SetAuthority<'info> {
-    #[account(address = global_pool.super_admin)]
-    pub admin: Signer<'info>,
-
+#[instruction(
+    params: InitPlayGameParams
+)]
+pub struct PlayGameToken<'info> {
     #[account(mut)]
-    pub global_pool: Account<'info, GlobalPool>,
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        address = global_authority.operation_authority
+    )]
+    pub operator: Signer<'info>,
+
+    #[account(
+        init,
+        space = 8 + PlayerPool::DATA_SIZE,
+        seeds = [&owner.key().as_ref(), PLAYER_POOL_SEED.as_bytes(), &params.game_session_id.to_be_bytes()[..]],
+        bump,
+        payer = operator
+    )]
+    pub player_pool: Account<'info, PlayerPool>,
+
+    #[account(
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        mut,
+        seeds = [TOKEN_CONFIG_SEED.as_bytes(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub token_config: Box<Account<'info, TokenConfig>>,
+
+    pub token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = owner,
+    )]
+    pub player_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub casino_token_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
-#[account]
-#[derive(Default)]
-pub struct PlayerPool {
-    pub bet: u64,           // 8
-    pub status: GameStatus, // 3
-    pub is_under: bool,     // 1
-    pub target_num: u8,     // 1
-    pub player: Pubkey,     // 32
+#[derive(Accounts)]
+#[instruction(
+    params: SetResultParams
+)]
+pub struct SetResultToken<'info> {
+    #[account(
+        mut,
+        address = global_authority.operation_authority
+    )]
+    pub operator: Signer<'info>,
+
+    /// CHECK:
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        mut,
+        seeds = [&owner.key().as_ref(), PLAYER_POOL_SEED.as_bytes(), &params.game_session_id.to_be_bytes()[..]],
+        bump,
+        close = operator
+    )]
+    pub player_pool: Account<'info, PlayerPool>,
+
+    #[account(address = player_pool.mint @ GameError::InvalidTokenMint)]
+    pub token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [TOKEN_CONFIG_SEED.as_bytes(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub token_config: Box<Account<'info, TokenConfig>>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = owner,
+    )]
+    pub player_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub casino_vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub casino_token_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(address = slot_hashes::ID)]
+    /// CHECK: Read with find_slot_hash, the sysvar is too large to deserialize whole
+    pub slot_hashes: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
 }
 
-impl PlayerPool {
-    pub const DATA_SIZE: usize = 8 + 3 + 1 + 1 + 32; // 45
+#[derive(Accounts)]
+pub struct AddToken<'info> {
+    #[account(
+        mut,
+        address = global_authority.update_authority
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    pub token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        space = 8 + TokenConfig::DATA_SIZE,
+        seeds = [TOKEN_CONFIG_SEED.as_bytes(), token_mint.key().as_ref()],
+        bump,
+        payer = admin
+    )]
+    pub token_config: Box<Account<'info, TokenConfig>>,
+
+    #[account(
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub casino_vault: AccountInfo<'info>,
+
+    #[account(
+        init,
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes(), token_mint.key().as_ref()],
+        bump,
+        payer = admin,
+        token::mint = token_mint,
+        token::authority = casino_vault,
+    )]
+    pub casino_token_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct SetTokenConfig<'info> {
+    #[account(address = global_pool.update_authority)]
+    pub admin: Signer<'info>,
+
+    pub global_pool: Account<'info, GlobalPool>,
+
+    #[account(mut)]
+    pub token_config: Account<'info, TokenConfig>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyTokenConfig<'info> {
+    #[account(
+        mut,
+        seeds = [TOKEN_CONFIG_SEED.as_bytes(), token_config.mint.as_ref()],
+        bump,
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    game_session_id: u64
+)]
+pub struct RefundExpiredBet<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        address = global_authority.operation_authority
+    )]
+    /// CHECK: Receives the player pool rent it paid in play_game
+    pub operator: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        mut,
+        seeds = [&owner.key().as_ref(), PLAYER_POOL_SEED.as_bytes(), &game_session_id.to_be_bytes()[..]],
+        bump,
+        close = operator
+    )]
+    pub player_pool: Account<'info, PlayerPool>,
+
+    #[account(
+        mut,
+        seeds = [&owner.key().as_ref(), VAULT_AUTHORITY_SEED.as_bytes(), &game_session_id.to_be_bytes()[..]],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub game_vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [PLAYER_BALANCE_SEED.as_bytes(), owner.key().as_ref()],
+        bump,
+    )]
+    pub player_balance: Option<Account<'info, PlayerBalance>>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub casino_vault: AccountInfo<'info>,
+
+    #[account(address = player_pool.vrf_randomness @ GameError::InvalidRandomnessAccount)]
+    /// CHECK: Vrf bets only, read with VrfRandomness::is_fulfilled_at
+    pub vrf_randomness: Option<UncheckedAccount<'info>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    game_session_id: u64
+)]
+pub struct ForfeitExpiredBet<'info> {
+    #[account(
+        mut,
+        address = global_authority.operation_authority
+    )]
+    /// CHECK: Receives the player pool rent it paid in play_game
+    pub operator: AccountInfo<'info>,
+
+    /// CHECK:
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        mut,
+        seeds = [&owner.key().as_ref(), PLAYER_POOL_SEED.as_bytes(), &game_session_id.to_be_bytes()[..]],
+        bump,
+        close = operator
+    )]
+    pub player_pool: Account<'info, PlayerPool>,
+
+    #[account(
+        mut,
+        seeds = [&owner.key().as_ref(), VAULT_AUTHORITY_SEED.as_bytes(), &game_session_id.to_be_bytes()[..]],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub game_vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub casino_vault: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    game_session_id: u64
+)]
+pub struct RefundExpiredTokenBet<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        address = global_authority.operation_authority
+    )]
+    /// CHECK: Receives the player pool rent it paid in play_game_token
+    pub operator: AccountInfo<'info>,
+
+    #[account(
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        mut,
+        seeds = [&owner.key().as_ref(), PLAYER_POOL_SEED.as_bytes(), &game_session_id.to_be_bytes()[..]],
+        bump,
+        close = operator
+    )]
+    pub player_pool: Account<'info, PlayerPool>,
+
+    #[account(address = player_pool.mint @ GameError::InvalidTokenMint)]
+    pub token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [TOKEN_CONFIG_SEED.as_bytes(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub token_config: Box<Account<'info, TokenConfig>>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = owner,
+    )]
+    pub player_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub casino_vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub casino_token_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetGlobalPool<'info> {
+    #[account(address = global_pool.update_authority)]
+    pub admin: Signer<'info>, // ADMIN_C must be this signer
+
+    #[account(mut)]
+    pub global_pool: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+pub struct SetAuthority<'info> {
+    #[account(address = global_pool.super_admin)]
+    pub admin: Signer<'info>,
+
+    #[account(mut)]
+    pub global_pool: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateGlobalPool<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+        owner = crate::ID,
+    )]
+    /// CHECK: Still in the legacy layout, so it can't be deserialized as a GlobalPool yet
+    pub global_pool: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyPendingConfig<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_pool: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    pub new_authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_pool: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+pub struct SetPause<'info> {
+    #[account(
+        constraint = admin.key() == global_pool.super_admin
+            || admin.key() == global_pool.guardian @ GameError::UnauthorizedGuardian
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(mut)]
+    pub global_pool: Account<'info, GlobalPool>,
+}
+
+#[account]
+#[derive(Default)]
+pub struct PlayerPool {
+    pub bet: u64,                   // 8
+    pub status: GameStatus,         // 3
+    pub is_under: bool,             // 1
+    pub target_num: u8,             // 1
+    pub player: Pubkey,             // 32
+    pub server_seed_hash: [u8; 32], // 32
+    pub client_seed: [u8; 32],      // 32
+    pub roll: u8,                   // 1
+    pub mint: Pubkey,               // 32, Pubkey::default() for SOL bets
+    pub bet_slot: u64,              // 8
+    pub bet_timestamp: i64,         // 8
+    pub potential_win: u64,         // 8, net amount the casino pays if this bet wins, plus a balance funded stake
+    pub rtp: u64,                   // 8, rtp in force when the bet was placed
+    pub multiplier: u64,            // 8, locked at bet time, MULTIPLIER_PRECISION scaled
+    pub payout: u64,                // 8, gross amount paid out if this bet wins
+    pub randomness_mode: RandomnessMode, // 1, how this bet is settled
+    pub vrf_randomness: Pubkey,     // 32, VRF request account in Vrf mode
+    pub game_kind: GameKind,        // 1
+    pub range_end: u8,              // 1, Range only
+    pub roll_count: u8,             // 1, rolls of `bet` each, 1 unless placed with play_multi
+    pub rolls: [u8; MAX_ROLLS],     // 10, filled up to roll_count at settlement
+    pub funded_from_balance: bool,  // 1, stake came from PlayerBalance and sits in the casino vault
+    pub vrf_program: Pubkey,        // 32, owner of vrf_randomness, fixed at bet time in Vrf mode
+}
+
+impl PlayerPool {
+    pub const DATA_SIZE: usize = 8 + 3 + 1 + 1 + 32 + 32 + 32 + 1 + 32 + 8 + 8 + 8 + 8 + 8 + 8 + 1 + 32 + 1 + 1 + 1 + MAX_ROLLS + 1 + 32; // 269
+
+    // Lamports held for the bet, `bet` per roll
+    pub fn total_stake(&self) -> Result<u64> {
+        self.bet
+            .checked_mul(self.roll_count as u64)
+            .ok_or(error!(GameError::MathOverflow))
+    }
+
+    pub fn rolled(&self) -> &[u8] {
+        &self.rolls[..self.roll_count as usize]
+    }
+
+    pub fn is_expired(&self, bet_timeout: i64, now: i64) -> Result<bool> {
+        let expires_at = self
+            .bet_timestamp
+            .checked_add(bet_timeout)
+            .ok_or(GameError::MathOverflow)?;
+
+        Ok(now >= expires_at)
+    }
+
+    // Check the revealed server seed against the commitment and record the roll
+    pub fn reveal(
+        &mut self,
+        server_seed: &[u8; 32],
+        slot_hash: &[u8; 32],
+        game_session_id: u64,
+    ) -> Result<u8> {
+        require!(
+            hash_server_seed(server_seed) == self.server_seed_hash,
+            GameError::InvalidServerSeed
+        );
+
+        self.record_roll(
+            RandomnessMode::CommitReveal,
+            &mix_slot_hash(server_seed, slot_hash),
+            game_session_id,
+        )
+    }
+
+    // Record the roll from fulfilled VRF output in place of a revealed server seed
+    pub fn reveal_vrf(&mut self, randomness: &[u8; 64], game_session_id: u64) -> Result<u8> {
+        let mut vrf_seed = [0u8; 32];
+        vrf_seed.copy_from_slice(&randomness[..32]);
+
+        self.record_roll(RandomnessMode::Vrf, &vrf_seed, game_session_id)
+    }
+
+    // Record the roll from the hash of the first slot after the bet
+    pub fn reveal_slot_hash(&mut self, slot_hash: &[u8; 32], game_session_id: u64) -> Result<u8> {
+        self.record_roll(RandomnessMode::SlotHash, slot_hash, game_session_id)
+    }
+
+    fn record_roll(
+        &mut self,
+        randomness_mode: RandomnessMode,
+        seed: &[u8; 32],
+        game_session_id: u64,
+    ) -> Result<u8> {
+        require!(
+            self.status == GameStatus::Active,
+            GameError::NotAllowedStatus
+        );
+
+        require!(
+            self.randomness_mode == randomness_mode,
+            GameError::WrongRandomnessMode
+        );
+
+        for index in 0..self.roll_count {
+            self.rolls[index as usize] =
+                compute_roll_at(seed, &self.client_seed, game_session_id, index);
+        }
+        self.roll = self.rolls[0];
+
+        Ok(self.roll)
+    }
+
+    pub fn is_winning_roll(&self, roll: u8) -> bool {
+        if roll as u64 >= ROLL_RANGE {
+            return false;
+        }
+
+        self.game_kind
+            .is_win(roll, self.target_num, self.range_end, self.is_under)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct AutoBetParams {
+    pub deposit: u64,
+    pub base_bet: u64,
+    pub max_bet: u64,
+    pub on_win_bps: u64,  // next bet as a share of the last one after a win, 0 resets to base_bet
+    pub on_loss_bps: u64, // same after a loss, 20_000 doubles it (martingale)
+    pub stop_loss: u64,   // stop once the session is down this much, 0 disables
+    pub stop_profit: u64, // stop once the session is up this much, 0 disables
+    pub game_kind: GameKind,
+    pub target_number: u8,
+    pub range_end: u8,
+    pub is_under: bool,
+    pub server_seed_hash: [u8; 32], // head of the operator's hash chain
+    pub client_seed: [u8; 32],
+}
+
+// Player-funded strategy the operator crank rolls without a player signature per bet.
+// The betting balance is held as lamports on this account on top of its rent.
+#[account]
+#[derive(Default)]
+pub struct AutoBetSession {
+    pub player: Pubkey,             // 32
+    pub balance: u64,               // 8
+    pub base_bet: u64,              // 8
+    pub current_bet: u64,           // 8
+    pub max_bet: u64,               // 8
+    pub on_win_bps: u64,            // 8
+    pub on_loss_bps: u64,           // 8
+    pub stop_loss: u64,             // 8
+    pub stop_profit: u64,           // 8
+    pub net_result: i64,            // 8, payouts minus stakes so far
+    pub nonce: u64,                 // 8, rolls played, mixed into each roll
+    pub game_kind: GameKind,        // 1
+    pub target_num: u8,             // 1
+    pub range_end: u8,              // 1
+    pub is_under: bool,             // 1
+    pub server_seed_hash: [u8; 32], // 32, each roll reveals its preimage, which becomes the next hash
+    pub client_seed: [u8; 32],      // 32
+    pub active: bool,               // 1
+    pub last_slot: u64,             // 8, slot of the start or last roll, the next roll mixes in the hash after it
+}
+
+impl AutoBetSession {
+    pub const DATA_SIZE: usize = 32 + 8 * 10 + 1 + 1 + 1 + 1 + 32 + 32 + 1 + 8; // 189
+
+    // The current bet as play_game would see it, so it goes through the same check_bet
+    pub fn bet_params(&self) -> InitPlayGameParams {
+        InitPlayGameParams {
+            game_kind: self.game_kind,
+            target_number: self.target_num,
+            range_end: self.range_end,
+            is_under: self.is_under,
+            bet_amount: self.current_bet,
+            game_session_id: self.nonce,
+            server_seed_hash: self.server_seed_hash,
+            client_seed: self.client_seed,
+            slot_hash: false,
+        }
+    }
+
+    // Size of the bet after a roll, following the on-win / on-loss strategy
+    pub fn next_bet(&self, is_win: bool) -> Result<u64> {
+        let bps = if is_win { self.on_win_bps } else { self.on_loss_bps };
+        if bps == 0 {
+            return Ok(self.base_bet);
+        }
+
+        let next_bet = (self.current_bet as u128)
+            .checked_mul(bps as u128)
+            .ok_or(GameError::MathOverflow)?
+            / BPS_DENOMINATOR as u128;
+
+        u64::try_from(next_bet).map_err(|_| error!(GameError::MathOverflow))
+    }
+
+    pub fn hit_stop(&self) -> bool {
+        let stop_loss_hit = self.stop_loss > 0
+            && self.net_result < 0
+            && self.net_result.unsigned_abs() >= self.stop_loss;
+        let stop_profit_hit = self.stop_profit > 0
+            && self.net_result > 0
+            && self.net_result.unsigned_abs() >= self.stop_profit;
+
+        stop_loss_hit
+            || stop_profit_hit
+            || self.current_bet > self.max_bet
+            || self.current_bet > self.balance
+    }
+}
+
+#[derive(Accounts)]
+pub struct StartAutoBet<'info> {
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    #[account(address = global_authority.operation_authority)]
+    pub operator: Signer<'info>,
+
+    #[account(
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        init,
+        space = 8 + AutoBetSession::DATA_SIZE,
+        seeds = [AUTO_BET_SEED.as_bytes(), player.key().as_ref()],
+        bump,
+        payer = player
+    )]
+    pub auto_bet_session: Account<'info, AutoBetSession>,
+
+    #[account(
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub casino_vault: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AutoBetRoll<'info> {
+    #[account(address = global_authority.operation_authority)]
+    pub operator: Signer<'info>,
+
+    #[account(
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        mut,
+        seeds = [AUTO_BET_SEED.as_bytes(), auto_bet_session.player.as_ref()],
+        bump,
+    )]
+    pub auto_bet_session: Account<'info, AutoBetSession>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub casino_vault: AccountInfo<'info>,
+
+    #[account(address = slot_hashes::ID)]
+    /// CHECK: Read with find_slot_hash, the sysvar is too large to deserialize whole
+    pub slot_hashes: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StopAutoBet<'info> {
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [AUTO_BET_SEED.as_bytes(), player.key().as_ref()],
+        bump,
+        close = player
+    )]
+    pub auto_bet_session: Account<'info, AutoBetSession>,
+}
+
+// Lamports a player keeps with the casino to bet from without a wallet transfer per bet.
+// Held on this account on top of its rent, like an auto-bet balance.
+#[account]
+#[derive(Default)]
+pub struct PlayerBalance {
+    pub player: Pubkey,            // 32
+    pub balance: u64,              // 8
+    pub allowance: u64,            // 8, stake the operator may still place without the player signing
+    pub allowance_expires_at: i64, // 8
+}
+
+impl PlayerBalance {
+    pub const DATA_SIZE: usize = 32 + 8 + 8 + 8; // 56
+
+    // Take a bet's stake out of the balance. Bets the player did not sign draw down the allowance.
+    pub fn debit(&mut self, amount: u64, player_signed: bool, now: i64) -> Result<()> {
+        if !player_signed {
+            require!(
+                now < self.allowance_expires_at && amount <= self.allowance,
+                GameError::InsufficientAllowance
+            );
+            self.allowance -= amount;
+        }
+
+        self.balance = self
+            .balance
+            .checked_sub(amount)
+            .ok_or(GameError::InsufficientUserBalance)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct DepositBalance<'info> {
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        space = 8 + PlayerBalance::DATA_SIZE,
+        seeds = [PLAYER_BALANCE_SEED.as_bytes(), player.key().as_ref()],
+        bump,
+        payer = player
+    )]
+    pub player_balance: Account<'info, PlayerBalance>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePlayerBalance<'info> {
+    #[account(mut)]
+    pub player: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PLAYER_BALANCE_SEED.as_bytes(), player.key().as_ref()],
+        bump,
+    )]
+    pub player_balance: Account<'info, PlayerBalance>,
+}
+
+#[derive(Accounts)]
+#[instruction(
+    params: InitPlayGameParams
+)]
+pub struct PlayGameBalance<'info> {
+    /// CHECK: Signs when present, otherwise a session key or the player's allowance backs the bet
+    pub owner: UncheckedAccount<'info>,
+
+    pub session_signer: Option<Signer<'info>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+    )]
+    pub session_token: Option<Account<'info, SessionToken>>,
+
+    #[account(
+        mut,
+        address = global_authority.operation_authority
+    )]
+    pub operator: Signer<'info>,
+
+    #[account(
+        init,
+        space = 8 + PlayerPool::DATA_SIZE,
+        seeds = [&owner.key().as_ref(), PLAYER_POOL_SEED.as_bytes(), &params.game_session_id.to_be_bytes()[..]],
+        bump,
+        payer = operator
+    )]
+    pub player_pool: Account<'info, PlayerPool>,
+
+    #[account(
+        mut,
+        seeds = [PLAYER_BALANCE_SEED.as_bytes(), owner.key().as_ref()],
+        bump,
+    )]
+    pub player_balance: Account<'info, PlayerBalance>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub casino_vault: AccountInfo<'info>,
+
+    /// CHECK: Vrf mode only, see PlayGame
+    pub vrf_randomness: Option<UncheckedAccount<'info>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct SessionTokenParams {
+    pub session_key: Pubkey,
+    pub expires_at: i64,
+    pub max_spend: u64, // total stake the key may place over its lifetime
+    pub max_bet: u64,   // stake of a single bet
+}
+
+// Delegates bet signing to an ephemeral key, e.g. one held by the browser, within spend caps.
+// Bets placed with it are funded from the owner's PlayerBalance.
+#[account]
+#[derive(Default)]
+pub struct SessionToken {
+    pub owner: Pubkey,       // 32
+    pub session_key: Pubkey, // 32
+    pub expires_at: i64,     // 8
+    pub max_spend: u64,      // 8
+    pub max_bet: u64,        // 8
+    pub spent: u64,          // 8
+}
+
+impl SessionToken {
+    pub const DATA_SIZE: usize = 32 + 32 + 8 + 8 + 8 + 8; // 96
+
+    // Count a bet's stake against the caps, failing if the key, expiry or limits don't allow it
+    pub fn authorize(&mut self, signer: Pubkey, amount: u64, now: i64) -> Result<()> {
+        require!(signer == self.session_key, GameError::InvalidSessionKey);
+        require!(now < self.expires_at, GameError::SessionExpired);
+
+        let spent = self
+            .spent
+            .checked_add(amount)
+            .ok_or(GameError::MathOverflow)?;
+        require!(
+            amount <= self.max_bet && spent <= self.max_spend,
+            GameError::SessionLimitExceeded
+        );
+        self.spent = spent;
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(
+    params: SessionTokenParams
+)]
+pub struct CreateSessionToken<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init,
+        space = 8 + SessionToken::DATA_SIZE,
+        seeds = [SESSION_TOKEN_SEED.as_bytes(), owner.key().as_ref(), params.session_key.as_ref()],
+        bump,
+        payer = owner
+    )]
+    pub session_token: Account<'info, SessionToken>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeSessionToken<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        close = owner
+    )]
+    pub session_token: Account<'info, SessionToken>,
+}
+
+// M-of-N signer set that must approve every casino vault withdrawal
+#[account]
+#[derive(Default)]
+pub struct TreasuryConfig {
+    pub signers: [Pubkey; MAX_TREASURY_SIGNERS], // 32 * 8
+    pub signer_count: u8,                        // 1
+    pub threshold: u8,                           // 1
+    pub proposal_expiry: i64,                    // 8, seconds a proposal stays open
+    pub version: u64,                            // 8, bumped on every signer set change
+    pub next_proposal_id: u64,                   // 8
+}
+
+impl TreasuryConfig {
+    pub const DATA_SIZE: usize = 32 * MAX_TREASURY_SIGNERS + 1 + 1 + 8 + 8 + 8; // 282
+
+    pub fn signer_index(&self, signer: &Pubkey) -> Result<usize> {
+        self.signers[..self.signer_count as usize]
+            .iter()
+            .position(|key| key == signer)
+            .ok_or_else(|| error!(GameError::NotTreasurySigner))
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct WithdrawalProposal {
+    pub id: u64,             // 8
+    pub proposer: Pubkey,    // 32
+    pub recipient: Pubkey,   // 32
+    pub mint: Pubkey,        // 32, Pubkey::default() for SOL
+    pub amount: u64,         // 8
+    pub approvals: u8,       // 1, bitmask over TreasuryConfig::signers
+    pub config_version: u64, // 8, signer set the approvals refer to
+    pub expires_at: i64,     // 8
+}
+
+impl WithdrawalProposal {
+    pub const DATA_SIZE: usize = 8 + 32 + 32 + 32 + 8 + 1 + 8 + 8; // 129
+
+    pub fn approval_count(&self) -> u8 {
+        self.approvals.count_ones() as u8
+    }
+
+    // Approvals only count against the signer set they were given under
+    pub fn check_open(&self, treasury_config: &TreasuryConfig, now: i64) -> Result<()> {
+        require!(
+            self.config_version == treasury_config.version,
+            GameError::StaleProposal
+        );
+        require!(now < self.expires_at, GameError::ProposalExpired);
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct SetTreasuryConfig<'info> {
+    #[account(
+        mut,
+        address = global_authority.super_admin
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        init_if_needed,
+        space = 8 + TreasuryConfig::DATA_SIZE,
+        seeds = [TREASURY_CONFIG_SEED.as_bytes()],
+        bump,
+        payer = admin
+    )]
+    pub treasury_config: Box<Account<'info, TreasuryConfig>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeWithdrawal<'info> {
+    #[account(
+        mut,
+        address = global_authority.finance_authority @ GameError::UnauthorizedFinanceAdmin
+    )]
+    pub proposer: Signer<'info>,
+
+    #[account(
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        mut,
+        seeds = [TREASURY_CONFIG_SEED.as_bytes()],
+        bump,
+    )]
+    pub treasury_config: Box<Account<'info, TreasuryConfig>>,
+
+    #[account(
+        init,
+        space = 8 + WithdrawalProposal::DATA_SIZE,
+        seeds = [WITHDRAWAL_PROPOSAL_SEED.as_bytes(), &treasury_config.next_proposal_id.to_be_bytes()],
+        bump,
+        payer = proposer
+    )]
+    pub proposal: Account<'info, WithdrawalProposal>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveWithdrawal<'info> {
+    pub signer: Signer<'info>,
+
+    #[account(
+        seeds = [TREASURY_CONFIG_SEED.as_bytes()],
+        bump,
+    )]
+    pub treasury_config: Box<Account<'info, TreasuryConfig>>,
+
+    #[account(
+        mut,
+        seeds = [WITHDRAWAL_PROPOSAL_SEED.as_bytes(), &proposal.id.to_be_bytes()],
+        bump,
+    )]
+    pub proposal: Account<'info, WithdrawalProposal>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteWithdrawal<'info> {
+    pub executor: Signer<'info>,
+
+    /// CHECK: Receives the rent of the closed proposal
+    #[account(
+        mut,
+        address = proposal.proposer
+    )]
+    pub proposer: AccountInfo<'info>,
+
+    /// CHECK: Must be the recipient approved in the proposal
+    #[account(
+        mut,
+        address = proposal.recipient
+    )]
+    pub recipient: AccountInfo<'info>,
+
+    #[account(
+        seeds = [ALLOWED_RECIPIENT_SEED.as_bytes(), recipient.key().as_ref()],
+        bump,
+    )]
+    pub allowed_recipient: Account<'info, AllowedRecipient>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        seeds = [TREASURY_CONFIG_SEED.as_bytes()],
+        bump,
+    )]
+    pub treasury_config: Box<Account<'info, TreasuryConfig>>,
+
+    #[account(
+        mut,
+        seeds = [WITHDRAWAL_PROPOSAL_SEED.as_bytes(), &proposal.id.to_be_bytes()],
+        bump,
+        close = proposer
+    )]
+    pub proposal: Account<'info, WithdrawalProposal>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub casino_vault: AccountInfo<'info>,
+
+    // Required once the liquidity pool exists, the withdrawal burns house shares
+    #[account(
+        mut,
+        seeds = [LP_MINT_SEED.as_bytes()],
+        bump,
+    )]
+    pub lp_mint: Option<Box<Account<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [HOUSE_SHARES_SEED.as_bytes()],
+        bump,
+    )]
+    pub house_shares: Option<Box<Account<'info, TokenAccount>>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteTokenWithdrawal<'info> {
+    pub executor: Signer<'info>,
+
+    /// CHECK: Receives the rent of the closed proposal
+    #[account(
+        mut,
+        address = proposal.proposer
+    )]
+    pub proposer: AccountInfo<'info>,
+
+    /// CHECK: Must be the recipient approved in the proposal
+    #[account(address = proposal.recipient)]
+    pub recipient: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = recipient,
+    )]
+    pub recipient_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [ALLOWED_RECIPIENT_SEED.as_bytes(), recipient.key().as_ref()],
+        bump,
+    )]
+    pub allowed_recipient: Account<'info, AllowedRecipient>,
+
+    #[account(
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        seeds = [TREASURY_CONFIG_SEED.as_bytes()],
+        bump,
+    )]
+    pub treasury_config: Box<Account<'info, TreasuryConfig>>,
+
+    #[account(
+        mut,
+        seeds = [WITHDRAWAL_PROPOSAL_SEED.as_bytes(), &proposal.id.to_be_bytes()],
+        bump,
+        close = proposer
+    )]
+    pub proposal: Account<'info, WithdrawalProposal>,
+
+    #[account(address = proposal.mint @ GameError::InvalidTokenMint)]
+    pub token_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        seeds = [TOKEN_CONFIG_SEED.as_bytes(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub token_config: Box<Account<'info, TokenConfig>>,
+
+    #[account(
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub casino_vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub casino_token_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CancelWithdrawal<'info> {
+    pub signer: Signer<'info>,
+
+    /// CHECK: Receives the rent of the closed proposal
+    #[account(
+        mut,
+        address = proposal.proposer
+    )]
+    pub proposer: AccountInfo<'info>,
+
+    #[account(
+        seeds = [TREASURY_CONFIG_SEED.as_bytes()],
+        bump,
+    )]
+    pub treasury_config: Box<Account<'info, TreasuryConfig>>,
+
+    #[account(
+        mut,
+        seeds = [WITHDRAWAL_PROPOSAL_SEED.as_bytes(), &proposal.id.to_be_bytes()],
+        bump,
+        close = proposer
+    )]
+    pub proposal: Account<'info, WithdrawalProposal>,
+}
+
+// Wallet the casino vault may send withdrawals to
+#[account]
+#[derive(Default)]
+pub struct AllowedRecipient {
+    pub recipient: Pubkey, // 32
+}
+
+impl AllowedRecipient {
+    pub const DATA_SIZE: usize = 32; // 32
+}
+
+#[derive(Accounts)]
+#[instruction(
+    recipient: Pubkey
+)]
+pub struct AddAllowedRecipient<'info> {
+    #[account(
+        mut,
+        address = global_authority.super_admin
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        init,
+        space = 8 + AllowedRecipient::DATA_SIZE,
+        seeds = [ALLOWED_RECIPIENT_SEED.as_bytes(), recipient.as_ref()],
+        bump,
+        payer = admin
+    )]
+    pub allowed_recipient: Account<'info, AllowedRecipient>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveAllowedRecipient<'info> {
+    #[account(
+        mut,
+        address = global_authority.super_admin
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        mut,
+        seeds = [ALLOWED_RECIPIENT_SEED.as_bytes(), allowed_recipient.recipient.as_ref()],
+        bump,
+        close = admin
+    )]
+    pub allowed_recipient: Account<'info, AllowedRecipient>,
+}
+
+#[derive(Accounts)]
+pub struct InitLiquidityPool<'info> {
+    #[account(
+        mut,
+        address = global_authority.super_admin
+    )]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub casino_vault: AccountInfo<'info>,
+
+    #[account(
+        init,
+        seeds = [LP_MINT_SEED.as_bytes()],
+        bump,
+        payer = admin,
+        mint::decimals = LP_DECIMALS,
+        mint::authority = casino_vault,
+    )]
+    pub lp_mint: Box<Account<'info, Mint>>,
+
+    // Shares backing the house bankroll, redeemed by treasury withdrawals
+    #[account(
+        init,
+        seeds = [HOUSE_SHARES_SEED.as_bytes()],
+        bump,
+        payer = admin,
+        token::mint = lp_mint,
+        token::authority = casino_vault,
+    )]
+    pub house_shares: Box<Account<'info, TokenAccount>>,
+
+    // Holds the shares of queued LP withdrawals until the crank burns them
+    #[account(
+        init,
+        seeds = [LP_ESCROW_SEED.as_bytes()],
+        bump,
+        payer = admin,
+        token::mint = lp_mint,
+        token::authority = casino_vault,
+    )]
+    pub lp_escrow: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct DepositLiquidity<'info> {
+    #[account(mut)]
+    pub provider: Signer<'info>,
+
+    #[account(
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub casino_vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [LP_MINT_SEED.as_bytes()],
+        bump,
+    )]
+    pub lp_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        token::mint = lp_mint,
+        token::authority = provider,
+    )]
+    pub provider_shares: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+// One LP exit waiting for its epoch boundary, its shares are escrowed in the meantime.
+// Each request gets its own account, paid for by the provider, so exits share no capacity.
+#[account]
+#[derive(Default)]
+pub struct LpWithdrawalTicket {
+    pub provider: Pubkey, // 32
+    pub nonce: u64,       // 8, picked by the provider to tell its tickets apart
+    pub shares: u64,      // 8
+    pub unlocks_at: i64,  // 8
+}
+
+impl LpWithdrawalTicket {
+    pub const DATA_SIZE: usize = 32 + 8 + 8 + 8; // 56
+}
+
+#[derive(Accounts)]
+#[instruction(
+    nonce: u64
+)]
+pub struct RequestLiquidityWithdrawal<'info> {
+    #[account(mut)]
+    pub provider: Signer<'info>,
+
+    #[account(
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        seeds = [LP_MINT_SEED.as_bytes()],
+        bump,
+    )]
+    pub lp_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        token::mint = lp_mint,
+        token::authority = provider,
+    )]
+    pub provider_shares: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [LP_ESCROW_SEED.as_bytes()],
+        bump,
+    )]
+    pub lp_escrow: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        space = 8 + LpWithdrawalTicket::DATA_SIZE,
+        seeds = [LP_WITHDRAWAL_SEED.as_bytes(), provider.key().as_ref(), &nonce.to_be_bytes()],
+        bump,
+        payer = provider
+    )]
+    pub ticket: Account<'info, LpWithdrawalTicket>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ProcessLiquidityWithdrawal<'info> {
+    pub cranker: Signer<'info>,
+
+    #[account(
+        seeds = [GLOBAL_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        mut,
+        seeds = [VAULT_AUTHORITY_SEED.as_bytes()],
+        bump,
+    )]
+    /// CHECK: This is not dangerous because we don't read or write from this account
+    pub casino_vault: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [LP_MINT_SEED.as_bytes()],
+        bump,
+    )]
+    pub lp_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [LP_ESCROW_SEED.as_bytes()],
+        bump,
+    )]
+    pub lp_escrow: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        address = ticket.provider @ GameError::InvalidLpProvider
+    )]
+    /// CHECK: Receives the payout and the ticket rent
+    pub provider: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [LP_WITHDRAWAL_SEED.as_bytes(), ticket.provider.as_ref(), &ticket.nonce.to_be_bytes()],
+        bump,
+        close = provider
+    )]
+    pub ticket: Account<'info, LpWithdrawalTicket>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
@@ -3,13 +3,46 @@ pub const CF_VERSION: &str = "0.3"; // hack proof
 pub const GLOBAL_AUTHORITY_SEED: &str = "global-authority";
 pub const VAULT_AUTHORITY_SEED: &str = "vault-authority";
 pub const PLAYER_POOL_SEED: &str = "player-pool";
+pub const TOKEN_CONFIG_SEED: &str = "token-config";
+pub const AUTO_BET_SEED: &str = "auto-bet";
+pub const PLAYER_BALANCE_SEED: &str = "player-balance";
+pub const SESSION_TOKEN_SEED: &str = "session-token";
+pub const TREASURY_CONFIG_SEED: &str = "treasury-config";
+pub const WITHDRAWAL_PROPOSAL_SEED: &str = "withdrawal-proposal";
+pub const ALLOWED_RECIPIENT_SEED: &str = "allowed-recipient";
+pub const LP_MINT_SEED: &str = "lp-mint";
+pub const HOUSE_SHARES_SEED: &str = "house-shares";
+pub const LP_ESCROW_SEED: &str = "lp-escrow";
+pub const LP_WITHDRAWAL_SEED: &str = "lp-withdrawal";
+pub const VRF_RANDOMNESS_SEED: &str = "orao-vrf-randomness-request"; // seed of the VRF program's request PDA
 
 pub const MAX_NAME_LENGTH: usize = 24;
 
 pub const PERMILLE: u64 = 1000;
+pub const RTP_DENOMINATOR: u64 = 100; // rtp is stored as a percentage
+pub const MULTIPLIER_PRECISION: u64 = 1_000_000_000; // payout multipliers are 1e9-scaled
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const LP_DECIMALS: u8 = 9; // LP shares start out 1:1 with lamports
 
 pub const RTP: u64 = 95; // 95%
 pub const MAX_WIN_AMOUNT: u64 = 10000000000;
 pub const MIN_BET_AMOUNT: u64 = 100000000;
 pub const MIN_NUMBER: u8 = 9;
 pub const MAX_NUMBER: u8 = 90;
+pub const BET_TIMEOUT: i64 = 60 * 60; // 1 hour
+pub const MAX_LIABILITY_BPS: u64 = 5_000; // open bets may owe at most 50% of the vault
+pub const CONFIG_TIMELOCK: i64 = 24 * 60 * 60; // 1 day notice before config changes apply
+pub const MIN_CONFIG_TIMELOCK: i64 = 60 * 60; // the notice period can't be cut below 1 hour
+pub const WITHDRAWAL_EPOCH_CAP: u64 = 100_000_000_000; // 100 SOL out of the casino vault per epoch
+pub const LP_EPOCH_DURATION: i64 = 24 * 60 * 60; // LP exits are paid at the first epoch boundary after the request
+pub const CONFIG_FIELD_COUNT: usize = 13;
+pub const MAX_TREASURY_SIGNERS: usize = 8; // approvals are a u8 bitmask over the signer set
+
+pub const ROLL_RANGE: u64 = 100; // rolls are 0..=99
+pub const MAX_ROLLS: usize = 10; // most rolls one play_multi bet can hold
+
+pub const PAUSE_BETTING: u8 = 1 << 0;
+pub const PAUSE_SETTLEMENT: u8 = 1 << 1;
+pub const PAUSE_WITHDRAWALS: u8 = 1 << 2;
+pub const PAUSE_LIQUIDITY: u8 = 1 << 3; // new LP deposits
+pub const PAUSE_ALL: u8 = PAUSE_BETTING | PAUSE_SETTLEMENT | PAUSE_WITHDRAWALS | PAUSE_LIQUIDITY;
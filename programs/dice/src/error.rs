@@ -28,4 +28,102 @@ pub enum GameError {
     UnauthorizedUpdateAdmin,
     #[msg("Invalid Target Number")] // 6012
     InvalidTargetNumber,
+    #[msg("Server seed does not match the committed hash")] // 6013
+    InvalidServerSeed,
+    #[msg("Math overflow")] // 6014
+    MathOverflow,
+    #[msg("Token mint does not match the bet")] // 6015
+    InvalidTokenMint,
+    #[msg("Bet has not expired yet")] // 6016
+    BetNotExpired,
+    #[msg("Invalid bet timeout")] // 6017
+    InvalidBetTimeout,
+    #[msg("Open bets would exceed the casino vault exposure limit")] // 6018
+    ExposureLimitExceeded,
+    #[msg("Invalid basis points value")] // 6019
+    InvalidBps,
+    #[msg("This action is paused")] // 6020
+    Paused,
+    #[msg("Only Super Admin or Guardian can call this")] // 6021
+    UnauthorizedGuardian,
+    #[msg("Invalid pause flags")] // 6022
+    InvalidPauseFlags,
+    #[msg("Signer is not the pending authority")] // 6023
+    NotPendingAuthority,
+    #[msg("No pending config change for this field")] // 6024
+    NoPendingConfig,
+    #[msg("Config change is still timelocked")] // 6025
+    ConfigTimelocked,
+    #[msg("Invalid config timelock")] // 6026
+    InvalidConfigTimelock,
+    #[msg("Bet was placed under a different randomness mode")] // 6027
+    WrongRandomnessMode,
+    #[msg("Invalid VRF randomness account")] // 6028
+    InvalidRandomnessAccount,
+    #[msg("VRF randomness has not been fulfilled")] // 6029
+    RandomnessNotFulfilled,
+    #[msg("Invalid VRF program")] // 6030
+    InvalidVrfProgram,
+    #[msg("Bet exceeds the slot hash mode limit")] // 6031
+    SlotHashBetTooLarge,
+    #[msg("Slot hash after the bet slot is not available")] // 6032
+    SlotHashNotAvailable,
+    #[msg("Invalid roll count")] // 6033
+    InvalidRollCount,
+    #[msg("Auto-bet session is not active")] // 6034
+    AutoBetInactive,
+    #[msg("Invalid auto-bet strategy")] // 6035
+    InvalidAutoBetParams,
+    #[msg("Bet exceeds the allowance the player granted the operator")] // 6036
+    InsufficientAllowance,
+    #[msg("Bet is funded from a player balance that was not passed")] // 6037
+    MissingPlayerBalance,
+    #[msg("Signer is not the session key of this session token")] // 6038
+    InvalidSessionKey,
+    #[msg("Session token has expired")] // 6039
+    SessionExpired,
+    #[msg("Bet exceeds the session token spend limits")] // 6040
+    SessionLimitExceeded,
+    #[msg("Invalid session token params")] // 6041
+    InvalidSessionParams,
+    #[msg("Signer is not in the treasury signer set")] // 6042
+    NotTreasurySigner,
+    #[msg("Invalid treasury signer set or threshold")] // 6043
+    InvalidTreasuryConfig,
+    #[msg("Signer already approved this withdrawal")] // 6044
+    AlreadyApproved,
+    #[msg("Withdrawal does not have enough approvals")] // 6045
+    ThresholdNotMet,
+    #[msg("Withdrawal proposal has expired")] // 6046
+    ProposalExpired,
+    #[msg("Treasury signer set changed after this proposal")] // 6047
+    StaleProposal,
+    #[msg("Withdrawal exceeds the per-epoch withdrawal cap")] // 6048
+    WithdrawalCapExceeded,
+    #[msg("Withdrawal would leave the casino vault below its reserve and open bet liability")] // 6049
+    ReserveFloorViolation,
+    #[msg("Liquidity pool accounts are missing or wrong")] // 6050
+    InvalidLiquidityPool,
+    #[msg("Casino vault has no equity left to back LP shares")] // 6051
+    PoolInsolvent,
+    #[msg("Not enough house LP shares to cover this withdrawal")] // 6052
+    InsufficientHouseShares,
+    #[msg("Invalid liquidity amount")] // 6053
+    InvalidLiquidityAmount,
+    #[msg("LP withdrawal has not reached its epoch boundary")] // 6054
+    LpWithdrawalNotDue,
+    #[msg("Account does not match the LP withdrawal ticket")] // 6055
+    InvalidLpProvider,
+    #[msg("Invalid LP epoch duration")] // 6056
+    InvalidLpEpochDuration,
+    #[msg("Only Super Admin can call this")] // 6057
+    UnauthorizedSuperAdmin,
+    #[msg("Global pool is not in the legacy layout")] // 6058
+    AlreadyMigrated,
+    #[msg("Slot hash mode can only be chosen per bet")] // 6059
+    SlotHashModeNotGlobal,
+    #[msg("Slot hash bets are forfeited, not refunded, once they expire")] // 6060
+    SlotHashBetNotRefundable,
+    #[msg("VRF bet has fulfilled randomness and must be settled, not refunded")] // 6061
+    VrfBetFulfilled,
 }
@@ -0,0 +1,316 @@
+use anchor_lang::prelude::*;
+
+use crate::account::{AuthorityRole, ConfigField, GameKind, RandomnessMode};
+
+#[event]
+pub struct BetPlaced {
+    pub player: Pubkey,
+    pub game_session_id: u64,
+    pub mint: Pubkey,
+    pub bet_amount: u64, // per roll
+    pub roll_count: u8,
+    pub game_kind: GameKind,
+    pub target_num: u8,
+    pub range_end: u8,
+    pub is_under: bool,
+    pub multiplier: u64,
+    pub server_seed_hash: [u8; 32],
+}
+
+#[event]
+pub struct BetSettled {
+    pub player: Pubkey,
+    pub game_session_id: u64,
+    pub mint: Pubkey,
+    pub game_kind: GameKind,
+    pub target_num: u8,
+    pub range_end: u8,
+    pub is_under: bool,
+    pub roll: u8,       // first roll
+    pub rolls: Vec<u8>, // every roll of a play_multi bet
+    pub wins: u8,
+    pub is_win: bool,
+    pub multiplier: u64,
+    pub payout: u64,
+}
+
+// Inputs of a commit-reveal roll, so anyone can recompute it after settlement
+#[event]
+pub struct ServerSeedRevealed {
+    pub player: Pubkey,
+    pub game_session_id: u64,
+    pub server_seed: [u8; 32],
+    pub slot_hash: [u8; 32],
+}
+
+#[event]
+pub struct BetRefunded {
+    pub player: Pubkey,
+    pub game_session_id: u64,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct ExpiredBetPaid {
+    pub player: Pubkey,
+    pub game_session_id: u64,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct BetForfeited {
+    pub player: Pubkey,
+    pub game_session_id: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct VaultWithdrawn {
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub remaining_balance: u64,
+}
+
+#[event]
+pub struct TokenVaultWithdrawn {
+    pub mint: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub remaining_balance: u64,
+}
+
+#[event]
+pub struct ConfigChanged {
+    pub field: ConfigField,
+    pub old_value: u64,
+    pub new_value: u64,
+}
+
+#[event]
+pub struct ConfigQueued {
+    pub field: ConfigField,
+    pub value: u64,
+    pub effective_at: i64,
+}
+
+#[event]
+pub struct ConfigCancelled {
+    pub field: ConfigField,
+    pub value: u64,
+}
+
+#[event]
+pub struct ConfigTimelockChanged {
+    pub old_timelock: i64,
+    pub new_timelock: i64,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub role: AuthorityRole,
+    pub current_authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct GlobalPoolMigrated {
+    pub admin: Pubkey,
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+#[event]
+pub struct AuthorityChanged {
+    pub role: AuthorityRole,
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+#[event]
+pub struct TokenConfigChanged {
+    pub mint: Pubkey,
+    pub min_bet_amount: u64,
+    pub max_win_amount: u64,
+}
+
+#[event]
+pub struct TokenConfigQueued {
+    pub mint: Pubkey,
+    pub min_bet_amount: u64,
+    pub max_win_amount: u64,
+    pub effective_at: i64,
+}
+
+#[event]
+pub struct TokenConfigCancelled {
+    pub mint: Pubkey,
+    pub min_bet_amount: u64,
+    pub max_win_amount: u64,
+}
+
+#[event]
+pub struct RandomnessModeQueued {
+    pub new_mode: RandomnessMode,
+    pub vrf_program: Pubkey,
+    pub effective_at: i64,
+}
+
+#[event]
+pub struct RandomnessModeCancelled {
+    pub mode: RandomnessMode,
+    pub vrf_program: Pubkey,
+}
+
+#[event]
+pub struct RandomnessModeChanged {
+    pub old_mode: RandomnessMode,
+    pub new_mode: RandomnessMode,
+    pub vrf_program: Pubkey,
+}
+
+#[event]
+pub struct PauseChanged {
+    pub admin: Pubkey,
+    pub old_flags: u8,
+    pub new_flags: u8,
+}
+
+#[event]
+pub struct AutoBetStarted {
+    pub player: Pubkey,
+    pub deposit: u64,
+    pub base_bet: u64,
+    pub server_seed_hash: [u8; 32],
+}
+
+#[event]
+pub struct AutoBetRolled {
+    pub player: Pubkey,
+    pub nonce: u64,
+    pub bet_amount: u64,
+    pub slot_hash: [u8; 32],
+    pub roll: u8,
+    pub is_win: bool,
+    pub payout: u64,
+    pub balance: u64,
+    pub net_result: i64,
+    pub active: bool, // false once a stop condition was hit
+}
+
+#[event]
+pub struct AutoBetStopped {
+    pub player: Pubkey,
+    pub refunded: u64,
+    pub net_result: i64,
+}
+
+#[event]
+pub struct BalanceDeposited {
+    pub player: Pubkey,
+    pub amount: u64,
+    pub balance: u64,
+}
+
+#[event]
+pub struct BalanceWithdrawn {
+    pub player: Pubkey,
+    pub amount: u64,
+    pub balance: u64,
+}
+
+#[event]
+pub struct AllowanceSet {
+    pub player: Pubkey,
+    pub allowance: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct SessionTokenCreated {
+    pub owner: Pubkey,
+    pub session_key: Pubkey,
+    pub expires_at: i64,
+    pub max_spend: u64,
+    pub max_bet: u64,
+}
+
+#[event]
+pub struct SessionTokenRevoked {
+    pub owner: Pubkey,
+    pub session_key: Pubkey,
+    pub spent: u64,
+}
+
+#[event]
+pub struct TreasuryConfigChanged {
+    pub signers: Vec<Pubkey>,
+    pub threshold: u8,
+    pub proposal_expiry: i64,
+    pub version: u64,
+}
+
+#[event]
+pub struct WithdrawalProposed {
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub recipient: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct WithdrawalApproved {
+    pub proposal_id: u64,
+    pub signer: Pubkey,
+    pub approvals: u8,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct WithdrawalCancelled {
+    pub proposal_id: u64,
+    pub cancelled_by: Pubkey,
+}
+
+#[event]
+pub struct AllowlistChanged {
+    pub recipient: Pubkey,
+    pub allowed: bool,
+}
+
+#[event]
+pub struct LiquidityPoolInitialized {
+    pub lp_mint: Pubkey,
+    pub house_shares: u64,
+    pub equity: u64,
+}
+
+#[event]
+pub struct LiquidityDeposited {
+    pub provider: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+    pub equity: u64, // before the deposit
+    pub supply: u64, // before the deposit
+}
+
+#[event]
+pub struct LiquidityWithdrawalRequested {
+    pub provider: Pubkey,
+    pub nonce: u64,
+    pub shares: u64,
+    pub unlocks_at: i64,
+}
+
+#[event]
+pub struct LiquidityWithdrawn {
+    pub provider: Pubkey,
+    pub nonce: u64,
+    pub shares: u64,
+    pub amount: u64,
+    pub equity: u64, // when the crank ran
+    pub supply: u64, // when the crank ran
+}
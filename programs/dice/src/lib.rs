@@ -5,11 +5,13 @@ use solana_program::pubkey::Pubkey;
 pub mod account;
 pub mod constants;
 pub mod error;
+pub mod event;
 pub mod utils;
 
 use account::*;
 use constants::*;
 use error::*;
+use event::*;
 use utils::*;
 
 declare_id!("BqQfYq22b1JFo2aDicPjhPAJisuLgwbLu38MiiS5XM8X");
@@ -17,15 +19,24 @@ declare_id!("BqQfYq22b1JFo2aDicPjhPAJisuLgwbLu38MiiS5XM8X");
 #[program]
 pub mod dice {
     use super::*;
-    use solana_program::native_token::LAMPORTS_PER_SOL;
+    /**
+        @disc: Create the global pool. The config timelock is set here rather than compiled in,
+            CONFIG_TIMELOCK for a production deployment and seconds for a local test validator.
+            It is readable on the global pool, and set_config_timelock can't take it below MIN_CONFIG_TIMELOCK.
+        @param:
+            config_timelock: Seconds config changes wait before they can be applied
+    */
     pub fn initialize(
         ctx: Context<Initialize>,
         operate_admin: Pubkey,
         financial_admin: Pubkey,
         update_admin: Pubkey,
+        config_timelock: i64,
     ) -> Result<()> {
         let global_authority = &mut ctx.accounts.global_authority;
 
+        require!(config_timelock > 0, GameError::InvalidConfigTimelock);
+
         // sol_transfer_user(
         //     ctx.accounts.admin.to_account_info().clone(),
         //     ctx.accounts.casino_vault.to_account_info().clone(),
@@ -42,296 +53,2348 @@ pub mod dice {
         global_authority.min_bet_amount = MIN_BET_AMOUNT;
         global_authority.min_num = MIN_NUMBER;
         global_authority.max_num = MAX_NUMBER;
+        global_authority.bet_timeout = BET_TIMEOUT;
+        global_authority.outstanding_liability = 0;
+        global_authority.max_liability_bps = MAX_LIABILITY_BPS;
+        global_authority.dynamic_max_win_bps = 0;
+        global_authority.guardian = Pubkey::default();
+        global_authority.pause_flags = 0;
+        global_authority.config_timelock = config_timelock;
+        global_authority.randomness_mode = RandomnessMode::CommitReveal;
+        global_authority.vrf_program = Pubkey::default();
+        global_authority.slot_hash_max_bet = 0; // slot hash mode starts disabled
+        global_authority.withdrawal_epoch_cap = WITHDRAWAL_EPOCH_CAP;
+        global_authority.withdrawal_epoch = 0;
+        global_authority.withdrawn_this_epoch = 0;
+        global_authority.min_reserve = 0;
+        global_authority.lp_mint = Pubkey::default();
+        global_authority.lp_epoch_duration = LP_EPOCH_DURATION;
+
+        Ok(())
+    }
+
+    /**
+        @disc: Grow a global pool created by the original deployment to the current layout and fill
+            the appended fields with the same defaults initialize sets. Super admin only, runs once.
+            Player pools are not migrated, so every open bet must be settled before the upgrade.
+    */
+    pub fn migrate_global_pool(ctx: Context<MigrateGlobalPool>) -> Result<()> {
+        let global_info = ctx.accounts.global_pool.to_account_info();
+        let old_size = global_info.data_len();
+        require!(
+            old_size == 8 + GlobalPool::LEGACY_DATA_SIZE,
+            GameError::AlreadyMigrated
+        );
+
+        {
+            let data = global_info.try_borrow_data()?;
+            require!(
+                data[..8] == *GlobalPool::DISCRIMINATOR,
+                ErrorCode::AccountDiscriminatorMismatch
+            );
+            let super_admin = Pubkey::try_from(&data[8..40]).unwrap();
+            require_keys_eq!(
+                super_admin,
+                ctx.accounts.admin.key(),
+                GameError::UnauthorizedSuperAdmin
+            );
+        }
 
+        let new_size = 8 + GlobalPool::DATA_SIZE;
+        resize_account(
+            global_info.clone(),
+            new_size,
+            ctx.accounts.admin.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+        )?;
+
+        // The legacy fields are a prefix of the current layout and the grown tail is zeroed,
+        // so only the non-zero defaults need filling in
+        let mut global_authority =
+            GlobalPool::try_deserialize(&mut &global_info.try_borrow_data()?[..])?;
+        global_authority.bet_timeout = BET_TIMEOUT;
+        global_authority.max_liability_bps = MAX_LIABILITY_BPS;
+        global_authority.config_timelock = CONFIG_TIMELOCK;
+        global_authority.randomness_mode = RandomnessMode::CommitReveal;
+        global_authority.withdrawal_epoch_cap = WITHDRAWAL_EPOCH_CAP;
+        global_authority.lp_epoch_duration = LP_EPOCH_DURATION;
+        global_authority.try_serialize(&mut &mut global_info.try_borrow_mut_data()?[..])?;
+
+        emit!(GlobalPoolMigrated {
+            admin: ctx.accounts.admin.key(),
+            old_size: old_size as u64,
+            new_size: new_size as u64,
+        });
         Ok(())
     }
     
     /**
-        @disc: Main function to flip coin.
+        @disc: Main function to place a bet on one of the GameKind tables.
+        @param:
+            game_kind:        OverUnder, CoinFlip, Range or Exact
+            target_number:    Over/under target, coin side (0 or 1), range start or exact number
+            range_end:        Last winning roll of a Range bet, ignored otherwise
+            is_under:         OverUnder only, win on a roll under (true) or over (false) the target
+            bet_amount:       The SOL amount to deposit
+            game_session_id:  Unique id of this bet for the player
+            server_seed_hash: sha256 of the operator's server seed, revealed at settlement
+            client_seed:      Player supplied seed mixed into the roll, along with the hash of the
+                              first slot after the bet so the co-signing operator can't know the roll
+            slot_hash:        Settle from the next slot hash instead, up to slot_hash_max_bet
+    */
+    pub fn play_game(ctx: Context<PlayGame>, params: InitPlayGameParams) -> Result<()> {
+        play_sol_game(ctx, params, 1)
+    }
+
+    /**
+        @disc: Place roll_count rolls of bet_amount each against the same target in one player pool.
+            A single settlement resolves every roll and pays the combined winnings.
+        @param:
+            params:     same as play_game, bet_amount is the stake of each roll
+            roll_count: Number of rolls, up to MAX_ROLLS
+    */
+    pub fn play_multi(
+        ctx: Context<PlayGame>,
+        params: InitPlayGameParams,
+        roll_count: u8,
+    ) -> Result<()> {
+        play_sol_game(ctx, params, roll_count)
+    }
+
+    /**
+        @disc: Place a bet funded from the player's PlayerBalance, signed by the operator so the player
+            doesn't sign every bet. Without the player's signature the bet must be signed by the session key
+            of a SessionToken passed with it, or else fit in an unexpired allowance.
+            The stake moves straight into the casino vault; settlement pays winnings back into the balance.
+        @param:
+            params: same as play_game
+    */
+    pub fn play_game_balance(
+        ctx: Context<PlayGameBalance>,
+        params: InitPlayGameParams,
+    ) -> Result<()> {
+        ctx.accounts.player_pool.funded_from_balance = true;
+        let total_stake = open_sol_bet(
+            &mut ctx.accounts.player_pool,
+            &mut ctx.accounts.global_authority,
+            &params,
+            1,
+            ctx.accounts.owner.key(),
+            ctx.accounts.casino_vault.lamports(),
+            ctx.accounts.vrf_randomness.as_ref(),
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let mut player_signed = ctx.accounts.owner.is_signer;
+        if !player_signed {
+            if let Some(session_token) = ctx.accounts.session_token.as_mut() {
+                let session_signer = ctx
+                    .accounts
+                    .session_signer
+                    .as_ref()
+                    .ok_or(GameError::InvalidSessionKey)?;
+                session_token.authorize(session_signer.key(), total_stake, now)?;
+                player_signed = true;
+            }
+        }
+
+        ctx.accounts
+            .player_balance
+            .debit(total_stake, player_signed, now)?;
+
+        program_account_transfer(
+            &ctx.accounts.player_balance.to_account_info(),
+            &ctx.accounts.casino_vault,
+            total_stake,
+        )?;
+
+        msg!(
+            "User's choice is {:?} {}",
+            params.game_kind,
+            params.target_number
+        );
+
+        Ok(())
+    }
+
+    /**
+        @disc: Settle the bet by revealing the server seed committed in play_game.
+            The roll is recomputed on-chain, with the hash of the first slot after bet_slot, and compared
+            against the player's target. Must run while that slot is in the SlotHashes window, otherwise
+            the bet can only be refunded.
         @param:
-            head_or_tail: indicate whether the player bet on head or tail       0: Tail, 1: Head
-            bet_amount:    The SOL amount to deposit
+            game_session_id: Id of the bet to settle
+            server_seed:     Preimage of the server_seed_hash stored in the player pool
     */
-    pub fn play_game(ctx: Context<PlayGame>, target_number: u8, is_under: bool, bet_amount: u64, game_session_id: u64) -> Result<()> {
+    pub fn set_result(ctx: Context<SetResult>, params: SetResultParams) -> Result<()> {
         let player_pool = &mut ctx.accounts.player_pool;
-        let player = &ctx.accounts.owner;
-        let global_authority = &ctx.accounts.global_authority;
+        let game_bump = ctx.bumps.game_vault;
+        let casino_bump = ctx.bumps.casino_vault;
+        let global_authority = &mut ctx.accounts.global_authority;
+        let game_session_id = params.game_session_id;
+        let owner_key = ctx.accounts.owner.key();
+        let session_bytes = game_session_id.to_be_bytes();
+        let game_vault_seeds: &[&[u8]] = &[
+            owner_key.as_ref(),
+            VAULT_AUTHORITY_SEED.as_bytes(),
+            &session_bytes[..],
+            &[game_bump],
+        ];
+
+        require!(
+            player_pool.mint == Pubkey::default(),
+            GameError::InvalidTokenMint
+        );
 
         require!(
-            global_authority.min_bet_amount <= bet_amount,
-            GameError::InvalidBetAmount
+            !global_authority.is_paused(PAUSE_SETTLEMENT),
+            GameError::Paused
         );
-        
+
+        let slot_hash = find_slot_hash(
+            &ctx.accounts.slot_hashes.try_borrow_data()?,
+            player_pool.bet_slot,
+        )
+        .ok_or(GameError::SlotHashNotAvailable)?;
+        player_pool.reveal(&params.server_seed, &slot_hash, game_session_id)?;
+
+        emit!(ServerSeedRevealed {
+            player: player_pool.player,
+            game_session_id,
+            server_seed: params.server_seed,
+            slot_hash,
+        });
+
+        settle_sol_bet(
+            player_pool,
+            global_authority,
+            SolSettlement {
+                owner: ctx.accounts.owner.to_account_info(),
+                operator: ctx.accounts.operator.to_account_info(),
+                game_vault: ctx.accounts.game_vault.to_account_info(),
+                casino_vault: ctx.accounts.casino_vault.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                game_vault_seeds,
+                casino_bump,
+                player_balance: ctx.accounts.player_balance.as_mut(),
+            },
+            game_session_id,
+        )?;
+
+        Ok(())
+    }
+
+    /**
+        @disc: Settle a Vrf mode bet from its fulfilled randomness account. The roll is derived
+            on-chain from the VRF output, so anyone may crank this once the request is fulfilled.
+        @param:
+            game_session_id: Id of the bet to settle
+    */
+    pub fn set_result_vrf(ctx: Context<SetResultVrf>, game_session_id: u64) -> Result<()> {
+        let player_pool = &mut ctx.accounts.player_pool;
+        let game_bump = ctx.bumps.game_vault;
+        let casino_bump = ctx.bumps.casino_vault;
+        let global_authority = &mut ctx.accounts.global_authority;
+        let owner_key = ctx.accounts.owner.key();
+        let session_bytes = game_session_id.to_be_bytes();
+        let game_vault_seeds: &[&[u8]] = &[
+            owner_key.as_ref(),
+            VAULT_AUTHORITY_SEED.as_bytes(),
+            &session_bytes[..],
+            &[game_bump],
+        ];
+
         require!(
-            global_authority.min_num < target_number,
-            GameError::InvalidTargetNumber
+            !global_authority.is_paused(PAUSE_SETTLEMENT),
+            GameError::Paused
         );
 
+        let vrf_randomness =
+            VrfRandomness::load(&ctx.accounts.vrf_randomness, &player_pool.vrf_program)?;
+        player_pool.reveal_vrf(&vrf_randomness.randomness, game_session_id)?;
+
+        settle_sol_bet(
+            player_pool,
+            global_authority,
+            SolSettlement {
+                owner: ctx.accounts.owner.to_account_info(),
+                operator: ctx.accounts.operator.to_account_info(),
+                game_vault: ctx.accounts.game_vault.to_account_info(),
+                casino_vault: ctx.accounts.casino_vault.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                game_vault_seeds,
+                casino_bump,
+                player_balance: ctx.accounts.player_balance.as_mut(),
+            },
+            game_session_id,
+        )?;
+
+        Ok(())
+    }
+
+    /**
+        @disc: Permissionless crank settling a small bet placed with slot_hash. The roll comes from
+            the hash of the first slot after bet_slot and the player's client seed. Must run while
+            bet_slot is still in the SlotHashes window, otherwise the stake is forfeited once the
+            bet expires.
+        @param:
+            game_session_id: Id of the bet to settle
+    */
+    pub fn set_result_slot_hash(ctx: Context<SetResultSlotHash>, game_session_id: u64) -> Result<()> {
+        let player_pool = &mut ctx.accounts.player_pool;
+        let game_bump = ctx.bumps.game_vault;
+        let casino_bump = ctx.bumps.casino_vault;
+        let global_authority = &mut ctx.accounts.global_authority;
+        let owner_key = ctx.accounts.owner.key();
+        let session_bytes = game_session_id.to_be_bytes();
+        let game_vault_seeds: &[&[u8]] = &[
+            owner_key.as_ref(),
+            VAULT_AUTHORITY_SEED.as_bytes(),
+            &session_bytes[..],
+            &[game_bump],
+        ];
+
         require!(
-            global_authority.max_num > target_number,
-            GameError::InvalidTargetNumber
+            !global_authority.is_paused(PAUSE_SETTLEMENT),
+            GameError::Paused
         );
 
-        let bet_amount_f64 = bet_amount as f64;
-        let rtp_f64 = global_authority.rtp as f64;
-        let rtp_ratio = rtp_f64 / 100.0;
+        let slot_hash = find_slot_hash(
+            &ctx.accounts.slot_hashes.try_borrow_data()?,
+            player_pool.bet_slot,
+        )
+        .ok_or(GameError::SlotHashNotAvailable)?;
+        player_pool.reveal_slot_hash(&slot_hash, game_session_id)?;
 
-        let new_balance;
-        if is_under == true {
-            let win_chance = target_number as f64 / 100.0;
-            let multiplier = (1.0 / win_chance) * rtp_ratio;
-            new_balance = bet_amount_f64 * multiplier;
-        } else {
-            let win_chance = (99 - target_number) as f64 / 100.0;
-            let multiplier = (1.0 / win_chance) * rtp_ratio;
-            new_balance = bet_amount_f64 * multiplier;
-        }
+        settle_sol_bet(
+            player_pool,
+            global_authority,
+            SolSettlement {
+                owner: ctx.accounts.owner.to_account_info(),
+                operator: ctx.accounts.operator.to_account_info(),
+                game_vault: ctx.accounts.game_vault.to_account_info(),
+                casino_vault: ctx.accounts.casino_vault.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                game_vault_seeds,
+                casino_bump,
+                player_balance: ctx.accounts.player_balance.as_mut(),
+            },
+            game_session_id,
+        )?;
+
+        Ok(())
+    }
 
-        let net_gain = new_balance - bet_amount_f64;
-        let net_gain_u64 = net_gain as u64;
-        let max_win_amount_u64 = global_authority.max_win_amount;
+    /**
+        @disc: Place a dice bet in an SPL token that has been enabled with add_token.
+            The stake goes straight into the casino token vault for that mint.
+        @param: same as play_game, bet_amount is in the token's base units
+    */
+    pub fn play_game_token(ctx: Context<PlayGameToken>, params: InitPlayGameParams) -> Result<()> {
+        let player_pool = &mut ctx.accounts.player_pool;
+        let global_authority = &ctx.accounts.global_authority;
+        let token_config = &mut ctx.accounts.token_config;
+        let bet_amount = params.bet_amount;
 
         require!(
-            net_gain_u64 < max_win_amount_u64,
-            GameError::InvalidBetAmountMaxWinAmountViolation
+            !global_authority.is_paused(PAUSE_BETTING),
+            GameError::Paused
         );
 
-        // require!(
-        //     (bet_amount * 2 * (global_authority.rtp / 100) - bet_amount)
-        //         < global_authority.max_win_amount,
-        //     GameError::InvalidBetAmountMaxWinAmountViolation
-        // );
+        require!(!params.slot_hash, GameError::WrongRandomnessMode);
+
+        let max_win_amount = global_authority.effective_max_win(
+            token_config.max_win_amount,
+            ctx.accounts.casino_token_vault.amount,
+        )?;
+
+        let (multiplier, payout, potential_win) = check_bet(
+            global_authority,
+            token_config.min_bet_amount,
+            max_win_amount,
+            &params,
+            1,
+        )?;
 
         require!(
-            ctx.accounts.owner.to_account_info().lamports() > bet_amount,
+            ctx.accounts.player_token_account.amount >= bet_amount,
             GameError::InsufficientUserBalance
         );
 
         require!(
-            ctx.accounts.casino_vault.to_account_info().lamports() > bet_amount,
+            ctx.accounts.casino_token_vault.amount > bet_amount,
             GameError::InsufficientCasinoVault
         );
 
-        // Transfer rent fee for PDA of player pool
-        sol_transfer_user(
-            ctx.accounts.operator.to_account_info().clone(),
-            player_pool.to_account_info().clone(),
-            ctx.accounts.system_program.to_account_info().clone(),
-            ctx.accounts.rent.minimum_balance(0),
+        token_config.outstanding_liability = check_exposure(
+            token_config.outstanding_liability,
+            potential_win,
+            ctx.accounts.casino_token_vault.amount,
+            global_authority.max_liability_bps,
         )?;
 
-        // Transfer bet_amount Sol to this PDA from User Wallet
-        sol_transfer_user(
+        token_transfer_user(
+            ctx.accounts.player_token_account.to_account_info(),
+            ctx.accounts.casino_token_vault.to_account_info(),
             ctx.accounts.owner.to_account_info(),
-            ctx.accounts.game_vault.to_account_info(),
-            ctx.accounts.system_program.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
             bet_amount,
         )?;
 
         player_pool.status = GameStatus::Active;
         player_pool.bet = bet_amount;
-        player_pool.target_num = target_number;
-        player_pool.is_under = is_under;
-        player_pool.player = player.key();
-
-        if is_under == true {
-            msg!(
-                "User's choice is under {}",
-                target_number
-            );
-        } else {
-            msg!(
-                "User's choice is over {}",
-                target_number
-            );
-        }
+        player_pool.roll_count = 1;
+        player_pool.game_kind = params.game_kind;
+        player_pool.target_num = params.target_number;
+        player_pool.range_end = params.range_end;
+        player_pool.is_under = params.is_under;
+        player_pool.player = ctx.accounts.owner.key();
+        player_pool.server_seed_hash = params.server_seed_hash;
+        player_pool.client_seed = params.client_seed;
+        player_pool.mint = ctx.accounts.token_mint.key();
+        player_pool.potential_win = potential_win;
+        player_pool.rtp = global_authority.rtp;
+        player_pool.multiplier = multiplier;
+        player_pool.payout = payout;
+        let clock = Clock::get()?;
+        player_pool.bet_slot = clock.slot;
+        player_pool.bet_timestamp = clock.unix_timestamp;
+        // Token bets are always settled by set_result_token's seed reveal
+        player_pool.randomness_mode = RandomnessMode::CommitReveal;
+
+        emit!(BetPlaced {
+            player: player_pool.player,
+            game_session_id: params.game_session_id,
+            mint: player_pool.mint,
+            bet_amount,
+            roll_count: 1,
+            game_kind: params.game_kind,
+            target_num: params.target_number,
+            range_end: params.range_end,
+            is_under: params.is_under,
+            multiplier,
+            server_seed_hash: params.server_seed_hash,
+        });
 
         Ok(())
     }
 
     /**
-    The setting result function to determine whether player Win or Lose
+        @disc: Settle a token bet by revealing the server seed. Wins are paid from the casino token vault.
+        @param: same as set_result
     */
-    pub fn set_result(ctx: Context<SetResult>, is_win: bool, game_session_id: u64) -> Result<()> {
+    pub fn set_result_token(ctx: Context<SetResultToken>, params: SetResultParams) -> Result<()> {
         let player_pool = &mut ctx.accounts.player_pool;
-        let game_bump = ctx.bumps.game_vault;
-        let casino_bump = ctx.bumps.casino_vault;
         let global_authority = &ctx.accounts.global_authority;
-        let game_vault = &mut ctx.accounts.game_vault;
-        let casino_vault = &mut ctx.accounts.casino_vault;
-        let vault_balance = game_vault.lamports();
-
-        let bet_amount_f64 = player_pool.bet as f64;
-        let rtp_f64 = global_authority.rtp as f64;
-        let rtp_ratio = rtp_f64 / 100.0;
-
-        let win_balance;
-        if player_pool.is_under == true {
-            let win_chance = player_pool.target_num as f64 / 100.0;
-            let multiplier = (1.0 / win_chance) * rtp_ratio;
-            win_balance = bet_amount_f64 * multiplier;
+        let casino_bump = ctx.bumps.casino_vault;
+        let game_session_id = params.game_session_id;
+
+        require!(
+            !global_authority.is_paused(PAUSE_SETTLEMENT),
+            GameError::Paused
+        );
+
+        let slot_hash = find_slot_hash(
+            &ctx.accounts.slot_hashes.try_borrow_data()?,
+            player_pool.bet_slot,
+        )
+        .ok_or(GameError::SlotHashNotAvailable)?;
+        let roll = player_pool.reveal(&params.server_seed, &slot_hash, game_session_id)?;
+
+        emit!(ServerSeedRevealed {
+            player: player_pool.player,
+            game_session_id,
+            server_seed: params.server_seed,
+            slot_hash,
+        });
+        let is_win = player_pool.is_winning_roll(roll);
+
+        // Settle on the terms locked in at bet time, not the current config
+        let multiplier = player_pool.multiplier;
+        let payout = player_pool.payout;
+
+        let token_config = &mut ctx.accounts.token_config;
+        token_config.outstanding_liability = token_config
+            .outstanding_liability
+            .saturating_sub(player_pool.potential_win);
+
+        if is_win {
+            token_transfer_with_signer(
+                ctx.accounts.casino_token_vault.to_account_info(),
+                ctx.accounts.player_token_account.to_account_info(),
+                ctx.accounts.casino_vault.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                &[&[VAULT_AUTHORITY_SEED.as_bytes(), &[casino_bump]]],
+                payout,
+            )?;
+
+            player_pool.status = GameStatus::Win;
         } else {
-            let win_chance = (99 - player_pool.target_num) as f64 / 100.0;
-            let multiplier = (1.0 / win_chance) * rtp_ratio;
-            win_balance = bet_amount_f64 * multiplier;
+            player_pool.status = GameStatus::Lose;
+        }
+
+        emit!(BetSettled {
+            player: player_pool.player,
+            game_session_id,
+            mint: player_pool.mint,
+            game_kind: player_pool.game_kind,
+            target_num: player_pool.target_num,
+            range_end: player_pool.range_end,
+            is_under: player_pool.is_under,
+            roll,
+            rolls: vec![roll],
+            wins: is_win as u8,
+            is_win,
+            multiplier,
+            payout: if is_win { payout } else { 0 },
+        });
+
+        Ok(())
+    }
+
+    /**
+        @disc: Close a bet the operator never settled, callable by the player once
+            global_authority.bet_timeout has passed since the bet. Only the operator can reveal a
+            commit-reveal bet, so one left to lapse pays as if every roll had won. A VRF bet whose
+            request was never fulfilled gets its stake back.
+        @param:
+            game_session_id: Id of the lapsed bet
+    */
+    pub fn refund_expired_bet(ctx: Context<RefundExpiredBet>, game_session_id: u64) -> Result<()> {
+        let player_pool = &ctx.accounts.player_pool;
+        let game_bump = ctx.bumps.game_vault;
+        let amount = player_pool.total_stake()?;
+        let owner_key = ctx.accounts.owner.key();
+        let session_bytes = game_session_id.to_be_bytes();
+        let game_vault_seeds: &[&[u8]] = &[
+            owner_key.as_ref(),
+            VAULT_AUTHORITY_SEED.as_bytes(),
+            &session_bytes[..],
+            &[game_bump],
+        ];
+
+        require!(
+            player_pool.status == GameStatus::Active,
+            GameError::NotAllowedStatus
+        );
+
+        require!(
+            player_pool.mint == Pubkey::default(),
+            GameError::InvalidTokenMint
+        );
+
+        // Anyone can settle a slot hash bet, so an unsettled one is a loss the player sat on
+        require!(
+            player_pool.randomness_mode != RandomnessMode::SlotHash,
+            GameError::SlotHashBetNotRefundable
+        );
+
+        require!(
+            player_pool.is_expired(
+                ctx.accounts.global_authority.bet_timeout,
+                Clock::get()?.unix_timestamp
+            )?,
+            GameError::BetNotExpired
+        );
+
+        let global_authority = &mut ctx.accounts.global_authority;
+        global_authority.outstanding_liability = global_authority
+            .outstanding_liability
+            .saturating_sub(player_pool.potential_win);
+
+        if player_pool.randomness_mode == RandomnessMode::CommitReveal {
+            let payout = player_pool
+                .payout
+                .checked_mul(player_pool.roll_count as u64)
+                .ok_or(GameError::MathOverflow)?;
+            let player = player_pool.player;
+
+            pay_sol_bet(
+                &mut ctx.accounts.player_pool,
+                SolSettlement {
+                    owner: ctx.accounts.owner.to_account_info(),
+                    operator: ctx.accounts.operator.to_account_info(),
+                    game_vault: ctx.accounts.game_vault.to_account_info(),
+                    casino_vault: ctx.accounts.casino_vault.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    game_vault_seeds,
+                    casino_bump: ctx.bumps.casino_vault,
+                    player_balance: ctx.accounts.player_balance.as_mut(),
+                },
+                payout,
+            )?;
+
+            emit!(ExpiredBetPaid {
+                player,
+                game_session_id,
+                mint: Pubkey::default(),
+                amount: payout,
+            });
+            return Ok(());
         }
 
-        if is_win == true {
-            // Transfer bet_amount Sol to this PDA from casino bank
+        // Only a request the oracle never answered is refunded, a fulfilled roll settles either way
+        let vrf_randomness = ctx
+            .accounts
+            .vrf_randomness
+            .as_ref()
+            .ok_or(GameError::InvalidRandomnessAccount)?;
+        require!(
+            !VrfRandomness::is_fulfilled_at(vrf_randomness, &player_pool.vrf_program)?,
+            GameError::VrfBetFulfilled
+        );
+
+        // A balance funded stake sits in the casino vault and goes back into the balance
+        if player_pool.funded_from_balance {
+            let player_balance = ctx
+                .accounts
+                .player_balance
+                .as_mut()
+                .ok_or(GameError::MissingPlayerBalance)?;
+
             sol_transfer_with_signer(
-                casino_vault.to_account_info(),
-                game_vault.to_account_info(),
+                ctx.accounts.casino_vault.to_account_info(),
+                player_balance.to_account_info(),
                 ctx.accounts.system_program.to_account_info(),
-                &[&[VAULT_AUTHORITY_SEED.as_bytes(), &[casino_bump]]],
-                win_balance as u64 - vault_balance,
+                &[&[VAULT_AUTHORITY_SEED.as_bytes(), &[ctx.bumps.casino_vault]]],
+                amount,
             )?;
+            player_balance.balance = player_balance
+                .balance
+                .checked_add(amount)
+                .ok_or(GameError::MathOverflow)?;
 
-            player_pool.status = GameStatus::Win;
+            emit!(BetRefunded {
+                player: player_pool.player,
+                game_session_id,
+                mint: Pubkey::default(),
+                amount,
+            });
+            return Ok(());
+        }
 
+        let residue = ctx.accounts.game_vault.lamports().saturating_sub(amount);
+        if residue > 0 {
             sol_transfer_with_signer(
-                game_vault.to_account_info(),
-                ctx.accounts.owner.to_account_info(),
+                ctx.accounts.game_vault.to_account_info(),
+                ctx.accounts.operator.to_account_info(),
                 ctx.accounts.system_program.to_account_info(),
-                &[&[ctx.accounts.owner.key().as_ref(), VAULT_AUTHORITY_SEED.as_bytes(), &game_session_id.to_be_bytes()[..], &[game_bump]]],
-                win_balance as u64,
+                &[game_vault_seeds],
+                residue,
             )?;
+        }
+
+        sol_transfer_with_signer(
+            ctx.accounts.game_vault.to_account_info(),
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            &[game_vault_seeds],
+            amount,
+        )?;
 
-            let dest_starting_lamports = ctx.accounts.operator.lamports();
-            **ctx.accounts.operator.lamports.borrow_mut() = dest_starting_lamports
-                .checked_add(player_pool.to_account_info().lamports())
-                .unwrap();
-            **player_pool.to_account_info().lamports.borrow_mut() = 0;
+        emit!(BetRefunded {
+            player: player_pool.player,
+            game_session_id,
+            mint: Pubkey::default(),
+            amount,
+        });
 
-            let player_pool_account_info = player_pool.to_account_info();
-            let mut player_pool_data = player_pool_account_info.try_borrow_mut_data()?;
-            player_pool_data.fill(0);
+        Ok(())
+    }
+
+    /**
+        @disc: Permissionless crank closing a slot hash bet nobody settled before it expired. The
+            player could have settled it themselves, so the stake goes to the casino vault rather
+            than back to the player, and a losing roll can't be turned into a refund by waiting.
+        @param:
+            game_session_id: Id of the bet to forfeit
+    */
+    pub fn forfeit_expired_bet(ctx: Context<ForfeitExpiredBet>, game_session_id: u64) -> Result<()> {
+        let player_pool = &mut ctx.accounts.player_pool;
+        let game_bump = ctx.bumps.game_vault;
+        let amount = player_pool.total_stake()?;
+        let owner_key = ctx.accounts.owner.key();
+        let session_bytes = game_session_id.to_be_bytes();
+        let game_vault_seeds: &[&[u8]] = &[
+            owner_key.as_ref(),
+            VAULT_AUTHORITY_SEED.as_bytes(),
+            &session_bytes[..],
+            &[game_bump],
+        ];
+
+        require!(
+            player_pool.status == GameStatus::Active,
+            GameError::NotAllowedStatus
+        );
+
+        require!(
+            player_pool.randomness_mode == RandomnessMode::SlotHash,
+            GameError::WrongRandomnessMode
+        );
+
+        require!(
+            player_pool.is_expired(
+                ctx.accounts.global_authority.bet_timeout,
+                Clock::get()?.unix_timestamp
+            )?,
+            GameError::BetNotExpired
+        );
+
+        let global_authority = &mut ctx.accounts.global_authority;
+        global_authority.outstanding_liability = global_authority
+            .outstanding_liability
+            .saturating_sub(player_pool.potential_win);
+
+        // A balance funded stake already sits in the casino vault
+        if !player_pool.funded_from_balance {
+            let residue = ctx.accounts.game_vault.lamports().saturating_sub(amount);
+            if residue > 0 {
+                sol_transfer_with_signer(
+                    ctx.accounts.game_vault.to_account_info(),
+                    ctx.accounts.operator.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                    &[game_vault_seeds],
+                    residue,
+                )?;
+            }
 
-        } else {
             sol_transfer_with_signer(
-                game_vault.to_account_info(),
-                casino_vault.to_account_info(),
+                ctx.accounts.game_vault.to_account_info(),
+                ctx.accounts.casino_vault.to_account_info(),
                 ctx.accounts.system_program.to_account_info(),
-                &[&[
-                    ctx.accounts.owner.key().as_ref(),
-                    VAULT_AUTHORITY_SEED.as_bytes(),
-                    &game_session_id.to_be_bytes()[..],
-                    &[game_bump],
-                ]],
-                vault_balance,
+                &[game_vault_seeds],
+                amount,
             )?;
+        }
 
-            player_pool.status = GameStatus::Lose;
-
-            let dest_starting_lamports = ctx.accounts.operator.lamports();
-            **ctx.accounts.operator.lamports.borrow_mut() = dest_starting_lamports
-                .checked_add(player_pool.to_account_info().lamports())
-                .unwrap();
-            **player_pool.to_account_info().lamports.borrow_mut() = 0;
+        player_pool.status = GameStatus::Lose;
 
-            let player_pool_account_info = player_pool.to_account_info();
-            let mut player_pool_data = player_pool_account_info.try_borrow_mut_data()?;
-            player_pool_data.fill(0);
-        }
+        emit!(BetForfeited {
+            player: player_pool.player,
+            game_session_id,
+            amount,
+        });
 
         Ok(())
     }
 
     /**
-        @disc: Admin can withdraw SOL from the PDA
+        @disc: Token counterpart of refund_expired_bet. The locked payout is paid from the casino token vault.
         @param:
-            amount: The sol amount to withdraw from this PDA
+            game_session_id: Id of the lapsed bet
     */
-    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
-        let global_authority = &ctx.accounts.global_authority;
-        let financial_authority = &ctx.accounts.financial_admin;
-        let recipient = &ctx.accounts.recipient;
+    pub fn refund_expired_token_bet(
+        ctx: Context<RefundExpiredTokenBet>,
+        game_session_id: u64,
+    ) -> Result<()> {
+        let player_pool = &ctx.accounts.player_pool;
         let casino_bump = ctx.bumps.casino_vault;
-        let casino_vault = &ctx.accounts.casino_vault;
 
         require!(
-            financial_authority.key() == global_authority.finance_authority,
-            GameError::UnauthorizedFinanceAdmin
+            player_pool.status == GameStatus::Active,
+            GameError::NotAllowedStatus
         );
 
         require!(
-            casino_vault.lamports() > amount,
-            GameError::InsufficientCasinoVault
+            player_pool.is_expired(
+                ctx.accounts.global_authority.bet_timeout,
+                Clock::get()?.unix_timestamp
+            )?,
+            GameError::BetNotExpired
         );
 
-        sol_transfer_with_signer(
+        let token_config = &mut ctx.accounts.token_config;
+        token_config.outstanding_liability = token_config
+            .outstanding_liability
+            .saturating_sub(player_pool.potential_win);
+
+        // Token bets are commit-reveal only, so a lapsed one pays out as a win
+        token_transfer_with_signer(
+            ctx.accounts.casino_token_vault.to_account_info(),
+            ctx.accounts.player_token_account.to_account_info(),
             ctx.accounts.casino_vault.to_account_info(),
-            recipient.to_account_info(),
-            ctx.accounts.system_program.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
             &[&[VAULT_AUTHORITY_SEED.as_bytes(), &[casino_bump]]],
-            amount,
+            player_pool.payout,
         )?;
 
-        let balance = ctx.accounts.casino_vault.to_account_info().lamports();
-
-        msg!("Remaining balance: {:?}", balance);
+        emit!(ExpiredBetPaid {
+            player: player_pool.player,
+            game_session_id,
+            mint: player_pool.mint,
+            amount: player_pool.payout,
+        });
 
         Ok(())
     }
 
-    pub fn set_rtp(ctx: Context<SetGlobalPool>, new_rtp: u64) -> Result<()> {
-        require!(new_rtp < 100, GameError::InvalidRtp);
+    /**
+        @disc: Super admin sets the signer set that must approve casino vault withdrawals.
+            Changing it voids the approvals of every open proposal.
+        @param:
+            signers:         Treasury signers, up to MAX_TREASURY_SIGNERS
+            threshold:       Approvals needed to execute a withdrawal
+            proposal_expiry: Seconds a proposal can gather approvals and be executed
+    */
+    pub fn set_treasury_config(
+        ctx: Context<SetTreasuryConfig>,
+        signers: Vec<Pubkey>,
+        threshold: u8,
+        proposal_expiry: i64,
+    ) -> Result<()> {
+        require!(
+            !signers.is_empty()
+                && signers.len() <= MAX_TREASURY_SIGNERS
+                && threshold > 0
+                && threshold as usize <= signers.len()
+                && proposal_expiry > 0,
+            GameError::InvalidTreasuryConfig
+        );
 
-        ctx.accounts.global_pool.rtp = new_rtp;
+        for (i, signer) in signers.iter().enumerate() {
+            require!(
+                !signers[..i].contains(signer),
+                GameError::InvalidTreasuryConfig
+            );
+        }
 
-        Ok(())
-    }
+        let treasury_config = &mut ctx.accounts.treasury_config;
+        treasury_config.signers = [Pubkey::default(); MAX_TREASURY_SIGNERS];
+        treasury_config.signers[..signers.len()].copy_from_slice(&signers);
+        treasury_config.signer_count = signers.len() as u8;
+        treasury_config.threshold = threshold;
+        treasury_config.proposal_expiry = proposal_expiry;
+        treasury_config.version += 1;
 
-    pub fn set_max_win_amount(ctx: Context<SetGlobalPool>, new_max_win_amount: u64) -> Result<()> {
-        ctx.accounts.global_pool.max_win_amount = new_max_win_amount;
+        emit!(TreasuryConfigChanged {
+            signers,
+            threshold,
+            proposal_expiry,
+            version: treasury_config.version,
+        });
         Ok(())
     }
 
-    pub fn set_min_bet_amount(ctx: Context<SetGlobalPool>, new_min_bet_amount: u64) -> Result<()> {
-        ctx.accounts.global_pool.min_bet_amount = new_min_bet_amount;
+    /**
+        @disc: The finance authority proposes withdrawing SOL or tokens from the casino vault. It must also
+            be a treasury signer, and its own approval is counted.
+        @param:
+            recipient: Account the funds are sent to on execution
+            mint:      Token mint to withdraw, Pubkey::default() for SOL
+            amount:    The amount to withdraw from the casino vault
+    */
+    pub fn propose_withdrawal(
+        ctx: Context<ProposeWithdrawal>,
+        recipient: Pubkey,
+        mint: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        let treasury_config = &mut ctx.accounts.treasury_config;
+        let index = treasury_config.signer_index(&ctx.accounts.proposer.key())?;
+
+        require!(amount > 0, GameError::InvalidBetAmount);
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.id = treasury_config.next_proposal_id;
+        proposal.proposer = ctx.accounts.proposer.key();
+        proposal.recipient = recipient;
+        proposal.mint = mint;
+        proposal.amount = amount;
+        proposal.approvals = 1 << index;
+        proposal.config_version = treasury_config.version;
+        proposal.expires_at = Clock::get()?
+            .unix_timestamp
+            .checked_add(treasury_config.proposal_expiry)
+            .ok_or(GameError::MathOverflow)?;
+
+        treasury_config.next_proposal_id += 1;
+
+        emit!(WithdrawalProposed {
+            proposal_id: proposal.id,
+            proposer: proposal.proposer,
+            recipient,
+            mint,
+            amount,
+            expires_at: proposal.expires_at,
+        });
         Ok(())
     }
-    
-    pub fn set_min_num(ctx: Context<SetGlobalPool>, new_min_num: u8) -> Result<()> {
+
+    pub fn approve_withdrawal(ctx: Context<ApproveWithdrawal>) -> Result<()> {
+        let treasury_config = &ctx.accounts.treasury_config;
+        let proposal = &mut ctx.accounts.proposal;
+        let signer = ctx.accounts.signer.key();
+        let index = treasury_config.signer_index(&signer)?;
+
+        proposal.check_open(treasury_config, Clock::get()?.unix_timestamp)?;
+
         require!(
-            new_min_num <= ctx.accounts.global_pool.max_num,
-            GameError::InvalidTargetNumber
+            proposal.approvals & (1 << index) == 0,
+            GameError::AlreadyApproved
         );
+        proposal.approvals |= 1 << index;
 
-        ctx.accounts.global_pool.min_num = new_min_num;
+        emit!(WithdrawalApproved {
+            proposal_id: proposal.id,
+            signer,
+            approvals: proposal.approval_count(),
+            threshold: treasury_config.threshold,
+        });
         Ok(())
     }
-    
-    pub fn set_max_num(ctx: Context<SetGlobalPool>, new_max_num: u8) -> Result<()> {
+
+    /**
+        @disc: Send an approved withdrawal once it has threshold approvals. Anyone can execute it.
+            The recipient must be on the allowlist and the amount within what is left of the epoch cap.
+    */
+    pub fn execute_withdrawal(ctx: Context<ExecuteWithdrawal>) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        let proposal = &ctx.accounts.proposal;
+        let recipient = &ctx.accounts.recipient;
+        let casino_bump = ctx.bumps.casino_vault;
+        let casino_vault = &ctx.accounts.casino_vault;
+        let amount = proposal.amount;
+
         require!(
-            new_max_num >= ctx.accounts.global_pool.min_num,
-            GameError::InvalidTargetNumber
+            !global_authority.is_paused(PAUSE_WITHDRAWALS),
+            GameError::Paused
         );
 
-        ctx.accounts.global_pool.max_num = new_max_num;
-        Ok(())
-    }
+        require!(
+            proposal.mint == Pubkey::default(),
+            GameError::InvalidTokenMint
+        );
 
-    pub fn set_operation_authority(
-        ctx: Context<SetAuthority>,
-        new_operation_authority: Pubkey,
-    ) -> Result<()> {
-        ctx.accounts.global_pool.operation_authority = new_operation_authority;
-        Ok(())
-    }
+        proposal.check_open(&ctx.accounts.treasury_config, Clock::get()?.unix_timestamp)?;
 
-    pub fn set_finance_authority(
-        ctx: Context<SetAuthority>,
-        new_finance_authority: Pubkey,
-    ) -> Result<()> {
-        ctx.accounts.global_pool.finance_authority = new_finance_authority;
-        Ok(())
+        require!(
+            proposal.approval_count() >= ctx.accounts.treasury_config.threshold,
+            GameError::ThresholdNotMet
+        );
+
+        global_authority.record_withdrawal(amount, Clock::get()?.epoch)?;
+
+        require!(
+            casino_vault.lamports() > amount,
+            GameError::InsufficientCasinoVault
+        );
+
+        require!(
+            casino_vault.lamports() - amount >= global_authority.reserve_floor()?,
+            GameError::ReserveFloorViolation
+        );
+
+        // With outside LPs in the vault the house can only take out what its own shares are worth
+        if global_authority.lp_mint != Pubkey::default() {
+            let (Some(lp_mint), Some(house_shares), Some(token_program)) = (
+                ctx.accounts.lp_mint.as_ref(),
+                ctx.accounts.house_shares.as_ref(),
+                ctx.accounts.token_program.as_ref(),
+            ) else {
+                return err!(GameError::InvalidLiquidityPool);
+            };
+
+            let equity = lp_equity(casino_vault.lamports(), global_authority.reserve_floor()?);
+            let shares = shares_for_sol(amount, lp_mint.supply, equity)?;
+            require!(
+                shares <= house_shares.amount,
+                GameError::InsufficientHouseShares
+            );
+
+            token_burn_with_signer(
+                lp_mint.to_account_info(),
+                house_shares.to_account_info(),
+                casino_vault.to_account_info(),
+                token_program.to_account_info(),
+                &[&[VAULT_AUTHORITY_SEED.as_bytes(), &[casino_bump]]],
+                shares,
+            )?;
+        }
+
+        sol_transfer_with_signer(
+            ctx.accounts.casino_vault.to_account_info(),
+            recipient.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            &[&[VAULT_AUTHORITY_SEED.as_bytes(), &[casino_bump]]],
+            amount,
+        )?;
+
+        let balance = ctx.accounts.casino_vault.to_account_info().lamports();
+
+        msg!("Remaining balance: {:?}", balance);
+
+        emit!(VaultWithdrawn {
+            recipient: recipient.key(),
+            amount,
+            remaining_balance: balance,
+        });
+
+        Ok(())
+    }
+
+    /**
+        @disc: Send an approved token withdrawal from the casino token vault. Anyone can execute it.
+            The tokens backing open bets of that mint stay in the vault. The epoch cap is in
+            lamports and does not apply here.
+    */
+    pub fn execute_token_withdrawal(ctx: Context<ExecuteTokenWithdrawal>) -> Result<()> {
+        let proposal = &ctx.accounts.proposal;
+        let casino_token_vault = &ctx.accounts.casino_token_vault;
+        let amount = proposal.amount;
+
+        require!(
+            !ctx.accounts.global_authority.is_paused(PAUSE_WITHDRAWALS),
+            GameError::Paused
+        );
+
+        proposal.check_open(&ctx.accounts.treasury_config, Clock::get()?.unix_timestamp)?;
+
+        require!(
+            proposal.approval_count() >= ctx.accounts.treasury_config.threshold,
+            GameError::ThresholdNotMet
+        );
+
+        require!(
+            casino_token_vault.amount >= amount,
+            GameError::InsufficientCasinoVault
+        );
+
+        require!(
+            casino_token_vault.amount - amount >= ctx.accounts.token_config.outstanding_liability,
+            GameError::ReserveFloorViolation
+        );
+
+        token_transfer_with_signer(
+            casino_token_vault.to_account_info(),
+            ctx.accounts.recipient_token_account.to_account_info(),
+            ctx.accounts.casino_vault.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            &[&[VAULT_AUTHORITY_SEED.as_bytes(), &[ctx.bumps.casino_vault]]],
+            amount,
+        )?;
+
+        let remaining_balance = casino_token_vault.amount - amount;
+
+        emit!(TokenVaultWithdrawn {
+            mint: proposal.mint,
+            recipient: proposal.recipient,
+            amount,
+            remaining_balance,
+        });
+
+        Ok(())
+    }
+
+    /**
+        @disc: Super admin opens the bankroll to outside liquidity providers. The house is issued shares
+            1:1 for the equity already in the casino vault, so LPs buy in at the same price.
+    */
+    pub fn init_liquidity_pool(ctx: Context<InitLiquidityPool>) -> Result<()> {
+        let casino_bump = ctx.bumps.casino_vault;
+        let global_authority = &mut ctx.accounts.global_authority;
+        // Priced like a deposit, see deposit_liquidity
+        let equity = lp_equity(
+            ctx.accounts.casino_vault.lamports(),
+            global_authority.min_reserve,
+        );
+
+        global_authority.lp_mint = ctx.accounts.lp_mint.key();
+
+        if equity > 0 {
+            token_mint_with_signer(
+                ctx.accounts.lp_mint.to_account_info(),
+                ctx.accounts.house_shares.to_account_info(),
+                ctx.accounts.casino_vault.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                &[&[VAULT_AUTHORITY_SEED.as_bytes(), &[casino_bump]]],
+                equity,
+            )?;
+        }
+
+        emit!(LiquidityPoolInitialized {
+            lp_mint: global_authority.lp_mint,
+            house_shares: equity,
+            equity,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Fund the bankroll with SOL for LP shares at the current share price.
+            Equity is the casino vault less min_reserve, so house profit and loss accrue to shares.
+            Blocked by PAUSE_LIQUIDITY.
+        @param:
+            amount: Lamports to deposit
+    */
+    pub fn deposit_liquidity(ctx: Context<DepositLiquidity>, amount: u64) -> Result<()> {
+        let casino_bump = ctx.bumps.casino_vault;
+        let supply = ctx.accounts.lp_mint.supply;
+        // Open bets are not discounted here. Their liability assumes every one of them wins, so
+        // equity only rises as they settle and shares bought at the discount would dilute holders.
+        let equity = lp_equity(
+            ctx.accounts.casino_vault.lamports(),
+            ctx.accounts.global_authority.min_reserve,
+        );
+
+        require!(
+            !ctx.accounts.global_authority.is_paused(PAUSE_LIQUIDITY),
+            GameError::Paused
+        );
+
+        require!(supply == 0 || equity > 0, GameError::PoolInsolvent);
+
+        let shares = shares_for_deposit(amount, supply, equity)?;
+        require!(shares > 0, GameError::InvalidLiquidityAmount);
+
+        sol_transfer_user(
+            ctx.accounts.provider.to_account_info(),
+            ctx.accounts.casino_vault.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            amount,
+        )?;
+
+        token_mint_with_signer(
+            ctx.accounts.lp_mint.to_account_info(),
+            ctx.accounts.provider_shares.to_account_info(),
+            ctx.accounts.casino_vault.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            &[&[VAULT_AUTHORITY_SEED.as_bytes(), &[casino_bump]]],
+            shares,
+        )?;
+
+        emit!(LiquidityDeposited {
+            provider: ctx.accounts.provider.key(),
+            amount,
+            shares,
+            equity,
+            supply,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Request an LP exit. The shares move into escrow and keep sharing house profit and loss
+            until process_liquidity_withdrawal pays them out after the next LP epoch boundary.
+            Each request is its own ticket account, its rent paid by the provider and refunded on payout.
+        @param:
+            nonce:  Any value not used by another open ticket of the provider
+            shares: LP shares to redeem
+    */
+    pub fn request_liquidity_withdrawal(
+        ctx: Context<RequestLiquidityWithdrawal>,
+        nonce: u64,
+        shares: u64,
+    ) -> Result<()> {
+        require!(shares > 0, GameError::InvalidLiquidityAmount);
+
+        let unlocks_at = ctx
+            .accounts
+            .global_authority
+            .next_lp_epoch(Clock::get()?.unix_timestamp)?;
+
+        token_transfer_user(
+            ctx.accounts.provider_shares.to_account_info(),
+            ctx.accounts.lp_escrow.to_account_info(),
+            ctx.accounts.provider.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            shares,
+        )?;
+
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.provider = ctx.accounts.provider.key();
+        ticket.nonce = nonce;
+        ticket.shares = shares;
+        ticket.unlocks_at = unlocks_at;
+
+        emit!(LiquidityWithdrawalRequested {
+            provider: ticket.provider,
+            nonce,
+            shares,
+            unlocks_at,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Permissionless crank paying out one due LP withdrawal ticket at the share price of the
+            moment it runs, then closing the ticket. A ticket that would take the casino vault below its
+            reserve floor stays open for a later crank.
+    */
+    pub fn process_liquidity_withdrawal(ctx: Context<ProcessLiquidityWithdrawal>) -> Result<()> {
+        let casino_bump = ctx.bumps.casino_vault;
+        let global_authority = &ctx.accounts.global_authority;
+        let ticket = &ctx.accounts.ticket;
+
+        require!(
+            !global_authority.is_paused(PAUSE_WITHDRAWALS),
+            GameError::Paused
+        );
+
+        require!(
+            ticket.unlocks_at <= Clock::get()?.unix_timestamp,
+            GameError::LpWithdrawalNotDue
+        );
+
+        let supply = ctx.accounts.lp_mint.supply;
+        let reserve_floor = global_authority.reserve_floor()?;
+        let equity = lp_equity(ctx.accounts.casino_vault.lamports(), reserve_floor);
+
+        // With nothing above the reserve floor the shares would be burned for nothing,
+        // so exits wait until the vault recovers
+        require!(equity > 0, GameError::PoolInsolvent);
+
+        let amount = sol_for_shares(ticket.shares, supply, equity)?;
+        require!(amount <= equity, GameError::ReserveFloorViolation);
+
+        token_burn_with_signer(
+            ctx.accounts.lp_mint.to_account_info(),
+            ctx.accounts.lp_escrow.to_account_info(),
+            ctx.accounts.casino_vault.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            &[&[VAULT_AUTHORITY_SEED.as_bytes(), &[casino_bump]]],
+            ticket.shares,
+        )?;
+
+        sol_transfer_with_signer(
+            ctx.accounts.casino_vault.to_account_info(),
+            ctx.accounts.provider.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            &[&[VAULT_AUTHORITY_SEED.as_bytes(), &[casino_bump]]],
+            amount,
+        )?;
+
+        emit!(LiquidityWithdrawn {
+            provider: ticket.provider,
+            nonce: ticket.nonce,
+            shares: ticket.shares,
+            amount,
+            equity,
+            supply,
+        });
+
+        Ok(())
+    }
+
+    /**
+        @disc: Super admin allows withdrawals to be sent to a wallet.
+        @param:
+            recipient: The wallet to allow
+    */
+    pub fn add_allowed_recipient(ctx: Context<AddAllowedRecipient>, recipient: Pubkey) -> Result<()> {
+        ctx.accounts.allowed_recipient.recipient = recipient;
+
+        emit!(AllowlistChanged {
+            recipient,
+            allowed: true,
+        });
+        Ok(())
+    }
+
+    pub fn remove_allowed_recipient(ctx: Context<RemoveAllowedRecipient>) -> Result<()> {
+        emit!(AllowlistChanged {
+            recipient: ctx.accounts.allowed_recipient.recipient,
+            allowed: false,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Any treasury signer can drop a proposal, and anyone can clear one that has expired
+            or was made under an older signer set. Rent goes back to the proposer.
+    */
+    pub fn cancel_withdrawal(ctx: Context<CancelWithdrawal>) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        let is_open = ctx
+            .accounts
+            .proposal
+            .check_open(&ctx.accounts.treasury_config, Clock::get()?.unix_timestamp)
+            .is_ok();
+
+        if is_open {
+            ctx.accounts.treasury_config.signer_index(&signer)?;
+        }
+
+        emit!(WithdrawalCancelled {
+            proposal_id: ctx.accounts.proposal.id,
+            cancelled_by: signer,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Fund an auto-bet session that the operator crank rolls without further player signatures.
+            The operator co-signs to commit the head of its server seed hash chain.
+        @param:
+            deposit:     SOL moved into the session balance
+            base_bet:    First bet, and the bet after a reset
+            max_bet:     The session stops rather than bet more than this
+            on_win_bps:  Next bet as a share of the last after a win, 0 resets to base_bet
+            on_loss_bps: Same after a loss
+            stop_loss:   Stop once down this much, 0 disables
+            stop_profit: Stop once up this much, 0 disables
+            game_kind, target_number, range_end, is_under: the bet rolled every time, as in play_game
+    */
+    pub fn start_auto_bet(ctx: Context<StartAutoBet>, params: AutoBetParams) -> Result<()> {
+        let global_authority = &ctx.accounts.global_authority;
+        let session = &mut ctx.accounts.auto_bet_session;
+
+        require!(
+            !global_authority.is_paused(PAUSE_BETTING),
+            GameError::Paused
+        );
+
+        require!(
+            params.base_bet > 0
+                && params.base_bet <= params.max_bet
+                && params.base_bet <= params.deposit,
+            GameError::InvalidAutoBetParams
+        );
+
+        session.player = ctx.accounts.player.key();
+        session.balance = params.deposit;
+        session.base_bet = params.base_bet;
+        session.current_bet = params.base_bet;
+        session.max_bet = params.max_bet;
+        session.on_win_bps = params.on_win_bps;
+        session.on_loss_bps = params.on_loss_bps;
+        session.stop_loss = params.stop_loss;
+        session.stop_profit = params.stop_profit;
+        session.net_result = 0;
+        session.nonce = 0;
+        session.game_kind = params.game_kind;
+        session.target_num = params.target_number;
+        session.range_end = params.range_end;
+        session.is_under = params.is_under;
+        session.server_seed_hash = params.server_seed_hash;
+        session.client_seed = params.client_seed;
+        session.active = true;
+        session.last_slot = Clock::get()?.slot;
+
+        // The first bet must already pass play_game's checks
+        let max_win_amount = global_authority.effective_max_win(
+            global_authority.max_win_amount,
+            ctx.accounts.casino_vault.lamports(),
+        )?;
+        check_bet(
+            global_authority,
+            global_authority.min_bet_amount,
+            max_win_amount,
+            &session.bet_params(),
+            1,
+        )?;
+
+        sol_transfer_user(
+            ctx.accounts.player.to_account_info(),
+            session.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            params.deposit,
+        )?;
+
+        emit!(AutoBetStarted {
+            player: session.player,
+            deposit: params.deposit,
+            base_bet: params.base_bet,
+            server_seed_hash: params.server_seed_hash,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Operator crank rolling the next auto-bet. Each roll reveals the preimage of the
+            session's server_seed_hash, which then becomes the commitment for the following roll.
+            The roll is keyed on the seed mixed with the hash of the first slot after the previous
+            roll, so it waits for that slot and must land within the SlotHashes window.
+        @param:
+            server_seed: Preimage of the session's current server_seed_hash
+    */
+    pub fn auto_bet_roll(ctx: Context<AutoBetRoll>, server_seed: [u8; 32]) -> Result<()> {
+        let global_authority = &ctx.accounts.global_authority;
+        let session = &mut ctx.accounts.auto_bet_session;
+        let casino_bump = ctx.bumps.casino_vault;
+
+        require!(session.active, GameError::AutoBetInactive);
+
+        require!(
+            !global_authority.is_paused(PAUSE_BETTING | PAUSE_SETTLEMENT),
+            GameError::Paused
+        );
+
+        require!(
+            hash_server_seed(&server_seed) == session.server_seed_hash,
+            GameError::InvalidServerSeed
+        );
+
+        let bet_amount = session.current_bet;
+        require!(
+            bet_amount <= session.balance,
+            GameError::InsufficientUserBalance
+        );
+
+        let vault_balance = ctx.accounts.casino_vault.lamports();
+        let max_win_amount =
+            global_authority.effective_max_win(global_authority.max_win_amount, vault_balance)?;
+        let (multiplier, payout, potential_win) = check_bet(
+            global_authority,
+            global_authority.min_bet_amount,
+            max_win_amount,
+            &session.bet_params(),
+            1,
+        )?;
+
+        // Resolved within this instruction, so it only has to fit next to the open bets
+        check_exposure(
+            global_authority.outstanding_liability,
+            potential_win,
+            vault_balance,
+            global_authority.max_liability_bps,
+        )?;
+
+        // The whole seed chain is fixed when the session starts, so each roll also mixes in
+        // the hash of a slot after the previous one that the operator could not know then
+        let slot_hash = find_slot_hash(
+            &ctx.accounts.slot_hashes.try_borrow_data()?,
+            session.last_slot,
+        )
+        .ok_or(GameError::SlotHashNotAvailable)?;
+
+        let nonce = session.nonce;
+        let roll = compute_roll(
+            &mix_slot_hash(&server_seed, &slot_hash),
+            &session.client_seed,
+            nonce,
+        );
+        let is_win = session
+            .game_kind
+            .is_win(roll, session.target_num, session.range_end, session.is_under);
+        let received = if is_win { payout } else { 0 };
+
+        msg!(
+            "Auto-bet {} rolled {} on {:?} at {} -> {}",
+            nonce,
+            roll,
+            session.game_kind,
+            multiplier,
+            if is_win { "win" } else { "lose" }
+        );
+
+        if received > bet_amount {
+            sol_transfer_with_signer(
+                ctx.accounts.casino_vault.to_account_info(),
+                session.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+                &[&[VAULT_AUTHORITY_SEED.as_bytes(), &[casino_bump]]],
+                received - bet_amount,
+            )?;
+        } else if bet_amount > received {
+            program_account_transfer(
+                &session.to_account_info(),
+                &ctx.accounts.casino_vault,
+                bet_amount - received,
+            )?;
+        }
+
+        session.balance = session
+            .balance
+            .checked_sub(bet_amount)
+            .and_then(|balance| balance.checked_add(received))
+            .ok_or(GameError::MathOverflow)?;
+        session.net_result = session
+            .net_result
+            .checked_add(received as i64 - bet_amount as i64)
+            .ok_or(GameError::MathOverflow)?;
+        session.server_seed_hash = server_seed;
+        session.nonce = nonce + 1;
+        session.last_slot = Clock::get()?.slot;
+        session.current_bet = session.next_bet(is_win)?;
+        session.active = !session.hit_stop();
+
+        emit!(AutoBetRolled {
+            player: session.player,
+            nonce,
+            bet_amount,
+            slot_hash,
+            roll,
+            is_win,
+            payout: received,
+            balance: session.balance,
+            net_result: session.net_result,
+            active: session.active,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: End an auto-bet session, returning the remaining balance and rent to the player.
+    */
+    pub fn stop_auto_bet(ctx: Context<StopAutoBet>) -> Result<()> {
+        let session = &ctx.accounts.auto_bet_session;
+
+        emit!(AutoBetStopped {
+            player: session.player,
+            refunded: session.balance,
+            net_result: session.net_result,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Authorize an ephemeral session key to sign play_game_balance for the owner until expires_at,
+            within a per-bet and a total stake cap.
+        @param:
+            params: session key and its limits
+    */
+    pub fn create_session_token(
+        ctx: Context<CreateSessionToken>,
+        params: SessionTokenParams,
+    ) -> Result<()> {
+        require!(
+            params.expires_at > Clock::get()?.unix_timestamp
+                && params.max_bet > 0
+                && params.max_bet <= params.max_spend,
+            GameError::InvalidSessionParams
+        );
+
+        let session_token = &mut ctx.accounts.session_token;
+        session_token.owner = ctx.accounts.owner.key();
+        session_token.session_key = params.session_key;
+        session_token.expires_at = params.expires_at;
+        session_token.max_spend = params.max_spend;
+        session_token.max_bet = params.max_bet;
+        session_token.spent = 0;
+
+        emit!(SessionTokenCreated {
+            owner: session_token.owner,
+            session_key: params.session_key,
+            expires_at: params.expires_at,
+            max_spend: params.max_spend,
+            max_bet: params.max_bet,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Close a session token, returning its rent to the owner. Its key can't place bets afterwards.
+    */
+    pub fn revoke_session_token(ctx: Context<RevokeSessionToken>) -> Result<()> {
+        let session_token = &ctx.accounts.session_token;
+
+        emit!(SessionTokenRevoked {
+            owner: session_token.owner,
+            session_key: session_token.session_key,
+            spent: session_token.spent,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Move SOL from the player's wallet into their PlayerBalance, creating it on first use.
+        @param:
+            amount: Lamports to deposit
+    */
+    pub fn deposit_balance(ctx: Context<DepositBalance>, amount: u64) -> Result<()> {
+        require!(amount > 0, GameError::InvalidBetAmount);
+
+        sol_transfer_user(
+            ctx.accounts.player.to_account_info(),
+            ctx.accounts.player_balance.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            amount,
+        )?;
+
+        let player_balance = &mut ctx.accounts.player_balance;
+        player_balance.player = ctx.accounts.player.key();
+        player_balance.balance = player_balance
+            .balance
+            .checked_add(amount)
+            .ok_or(GameError::MathOverflow)?;
+
+        emit!(BalanceDeposited {
+            player: player_balance.player,
+            amount,
+            balance: player_balance.balance,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Move SOL from the player's PlayerBalance back to their wallet.
+        @param:
+            amount: Lamports to withdraw
+    */
+    pub fn withdraw_balance(ctx: Context<UpdatePlayerBalance>, amount: u64) -> Result<()> {
+        let player_balance = &mut ctx.accounts.player_balance;
+
+        player_balance.balance = player_balance
+            .balance
+            .checked_sub(amount)
+            .ok_or(GameError::InsufficientUserBalance)?;
+
+        program_account_transfer(
+            &player_balance.to_account_info(),
+            &ctx.accounts.player.to_account_info(),
+            amount,
+        )?;
+
+        emit!(BalanceWithdrawn {
+            player: player_balance.player,
+            amount,
+            balance: player_balance.balance,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Let the operator place bets from the PlayerBalance without the player signing, up to a total stake.
+            Replaces any previous allowance; set it to 0 to revoke.
+        @param:
+            allowance:  Total stake the operator may place
+            expires_at: Unix timestamp after which the allowance can't be used
+    */
+    pub fn set_balance_allowance(
+        ctx: Context<UpdatePlayerBalance>,
+        allowance: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        let player_balance = &mut ctx.accounts.player_balance;
+        player_balance.allowance = allowance;
+        player_balance.allowance_expires_at = expires_at;
+
+        emit!(AllowanceSet {
+            player: player_balance.player,
+            allowance,
+            expires_at,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: The set_* config instructions below queue the change behind global_authority.config_timelock.
+            It takes effect when anyone calls apply_pending_config after the notice period.
+    */
+    pub fn set_rtp(ctx: Context<SetGlobalPool>, new_rtp: u64) -> Result<()> {
+        queue_config_change(&mut ctx.accounts.global_pool, ConfigField::Rtp, new_rtp)
+    }
+
+    pub fn set_max_win_amount(ctx: Context<SetGlobalPool>, new_max_win_amount: u64) -> Result<()> {
+        queue_config_change(
+            &mut ctx.accounts.global_pool,
+            ConfigField::MaxWinAmount,
+            new_max_win_amount,
+        )
+    }
+
+    pub fn set_min_bet_amount(ctx: Context<SetGlobalPool>, new_min_bet_amount: u64) -> Result<()> {
+        queue_config_change(
+            &mut ctx.accounts.global_pool,
+            ConfigField::MinBetAmount,
+            new_min_bet_amount,
+        )
+    }
+    
+    pub fn set_min_num(ctx: Context<SetGlobalPool>, new_min_num: u8) -> Result<()> {
+        queue_config_change(
+            &mut ctx.accounts.global_pool,
+            ConfigField::MinNum,
+            new_min_num as u64,
+        )
+    }
+    
+    pub fn set_max_num(ctx: Context<SetGlobalPool>, new_max_num: u8) -> Result<()> {
+        queue_config_change(
+            &mut ctx.accounts.global_pool,
+            ConfigField::MaxNum,
+            new_max_num as u64,
+        )
+    }
+
+    pub fn set_bet_timeout(ctx: Context<SetGlobalPool>, new_bet_timeout: i64) -> Result<()> {
+        require!(new_bet_timeout > 0, GameError::InvalidBetTimeout);
+
+        queue_config_change(
+            &mut ctx.accounts.global_pool,
+            ConfigField::BetTimeout,
+            new_bet_timeout as u64,
+        )
+    }
+
+    pub fn set_max_liability_bps(ctx: Context<SetGlobalPool>, new_max_liability_bps: u64) -> Result<()> {
+        queue_config_change(
+            &mut ctx.accounts.global_pool,
+            ConfigField::MaxLiabilityBps,
+            new_max_liability_bps,
+        )
+    }
+
+    /**
+        @disc: Scale the max net win with the bankroll instead of the static max_win_amount.
+        @param:
+            new_dynamic_max_win_bps: Share of the vault balance a single bet may win, 0 to disable
+    */
+    pub fn set_dynamic_max_win_bps(
+        ctx: Context<SetGlobalPool>,
+        new_dynamic_max_win_bps: u64,
+    ) -> Result<()> {
+        queue_config_change(
+            &mut ctx.accounts.global_pool,
+            ConfigField::DynamicMaxWinBps,
+            new_dynamic_max_win_bps,
+        )
+    }
+
+    pub fn set_slot_hash_max_bet(
+        ctx: Context<SetGlobalPool>,
+        new_slot_hash_max_bet: u64,
+    ) -> Result<()> {
+        queue_config_change(
+            &mut ctx.accounts.global_pool,
+            ConfigField::SlotHashMaxBet,
+            new_slot_hash_max_bet,
+        )
+    }
+
+    pub fn set_withdrawal_epoch_cap(
+        ctx: Context<SetGlobalPool>,
+        new_withdrawal_epoch_cap: u64,
+    ) -> Result<()> {
+        queue_config_change(
+            &mut ctx.accounts.global_pool,
+            ConfigField::WithdrawalEpochCap,
+            new_withdrawal_epoch_cap,
+        )
+    }
+
+    pub fn set_lp_epoch_duration(
+        ctx: Context<SetGlobalPool>,
+        new_lp_epoch_duration: i64,
+    ) -> Result<()> {
+        require!(new_lp_epoch_duration > 0, GameError::InvalidLpEpochDuration);
+
+        queue_config_change(
+            &mut ctx.accounts.global_pool,
+            ConfigField::LpEpochDuration,
+            new_lp_epoch_duration as u64,
+        )
+    }
+
+    pub fn set_min_reserve(ctx: Context<SetGlobalPool>, new_min_reserve: u64) -> Result<()> {
+        queue_config_change(
+            &mut ctx.accounts.global_pool,
+            ConfigField::MinReserve,
+            new_min_reserve,
+        )
+    }
+
+    /**
+        @disc: Permissionless crank that applies a queued config change once its timelock has passed.
+        @param:
+            field: The config field to apply
+    */
+    pub fn apply_pending_config(ctx: Context<ApplyPendingConfig>, field: ConfigField) -> Result<()> {
+        let global_pool = &mut ctx.accounts.global_pool;
+        let pending = global_pool.pending_configs[field as usize];
+
+        require!(pending.effective_at != 0, GameError::NoPendingConfig);
+
+        require!(
+            Clock::get()?.unix_timestamp >= pending.effective_at,
+            GameError::ConfigTimelocked
+        );
+
+        let old_value = global_pool.apply_config(field, pending.value)?;
+        global_pool.pending_configs[field as usize] = PendingConfig::default();
+
+        emit!(ConfigChanged {
+            field,
+            old_value,
+            new_value: pending.value,
+        });
+        Ok(())
+    }
+
+    pub fn cancel_pending_config(ctx: Context<SetGlobalPool>, field: ConfigField) -> Result<()> {
+        let global_pool = &mut ctx.accounts.global_pool;
+        let pending = global_pool.pending_configs[field as usize];
+
+        require!(pending.effective_at != 0, GameError::NoPendingConfig);
+
+        global_pool.pending_configs[field as usize] = PendingConfig::default();
+
+        emit!(ConfigCancelled {
+            field,
+            value: pending.value,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Change the notice period of config changes. A longer one applies at once, a shorter
+            one is queued behind the current timelock like any other config change.
+        @param:
+            new_config_timelock: Seconds of notice, at least MIN_CONFIG_TIMELOCK
+    */
+    pub fn set_config_timelock(ctx: Context<SetAuthority>, new_config_timelock: i64) -> Result<()> {
+        let global_pool = &mut ctx.accounts.global_pool;
+
+        require!(
+            new_config_timelock >= MIN_CONFIG_TIMELOCK,
+            GameError::InvalidConfigTimelock
+        );
+
+        let old_timelock = global_pool.config_timelock;
+        if new_config_timelock < old_timelock {
+            return queue_config_change(
+                global_pool,
+                ConfigField::ConfigTimelock,
+                new_config_timelock as u64,
+            );
+        }
+
+        global_pool.config_timelock = new_config_timelock;
+
+        emit!(ConfigTimelockChanged {
+            old_timelock,
+            new_timelock: new_config_timelock,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Enable betting in an SPL token. Creates the per-mint config and casino token vault.
+        @param:
+            min_bet_amount: Minimum bet in the token's base units
+            max_win_amount: Maximum net win in the token's base units
+    */
+    pub fn add_token(ctx: Context<AddToken>, min_bet_amount: u64, max_win_amount: u64) -> Result<()> {
+        let token_config = &mut ctx.accounts.token_config;
+
+        token_config.mint = ctx.accounts.token_mint.key();
+        token_config.min_bet_amount = min_bet_amount;
+        token_config.max_win_amount = max_win_amount;
+
+        emit!(TokenConfigChanged {
+            mint: token_config.mint,
+            min_bet_amount,
+            max_win_amount,
+        });
+
+        Ok(())
+    }
+
+    /**
+        @disc: Queue new limits for a token. They apply through apply_token_config once the config
+            timelock has passed, queueing again replaces the pending limits.
+        @param:
+            min_bet_amount: Minimum bet in the token's base units
+            max_win_amount: Maximum net win in the token's base units
+    */
+    pub fn set_token_config(
+        ctx: Context<SetTokenConfig>,
+        min_bet_amount: u64,
+        max_win_amount: u64,
+    ) -> Result<()> {
+        let token_config = &mut ctx.accounts.token_config;
+        let effective_at = ctx
+            .accounts
+            .global_pool
+            .config_effective_at(Clock::get()?.unix_timestamp)?;
+
+        token_config.pending = PendingTokenConfig {
+            min_bet_amount,
+            max_win_amount,
+            effective_at,
+        };
+
+        emit!(TokenConfigQueued {
+            mint: token_config.mint,
+            min_bet_amount,
+            max_win_amount,
+            effective_at,
+        });
+
+        Ok(())
+    }
+
+    /**
+        @disc: Permissionless crank that applies a token's queued limits once their timelock has passed.
+    */
+    pub fn apply_token_config(ctx: Context<ApplyTokenConfig>) -> Result<()> {
+        let token_config = &mut ctx.accounts.token_config;
+        let pending = token_config.pending;
+
+        require!(pending.effective_at != 0, GameError::NoPendingConfig);
+
+        require!(
+            Clock::get()?.unix_timestamp >= pending.effective_at,
+            GameError::ConfigTimelocked
+        );
+
+        token_config.min_bet_amount = pending.min_bet_amount;
+        token_config.max_win_amount = pending.max_win_amount;
+        token_config.pending = PendingTokenConfig::default();
+
+        emit!(TokenConfigChanged {
+            mint: token_config.mint,
+            min_bet_amount: pending.min_bet_amount,
+            max_win_amount: pending.max_win_amount,
+        });
+
+        Ok(())
+    }
+
+    /**
+        @disc: Update admin drops a token's queued limits before they apply.
+    */
+    pub fn cancel_token_config(ctx: Context<SetTokenConfig>) -> Result<()> {
+        let token_config = &mut ctx.accounts.token_config;
+        let pending = token_config.pending;
+
+        require!(pending.effective_at != 0, GameError::NoPendingConfig);
+
+        token_config.pending = PendingTokenConfig::default();
+
+        emit!(TokenConfigCancelled {
+            mint: token_config.mint,
+            min_bet_amount: pending.min_bet_amount,
+            max_win_amount: pending.max_win_amount,
+        });
+        Ok(())
     }
 
-    pub fn set_update_authority(
-        ctx: Context<SetAuthority>,
-        new_update_authority: Pubkey,
+    /**
+        @disc: Queue how new SOL bets are settled, applied through apply_randomness_mode once the
+            config timelock has passed. Open bets keep the mode they were placed under, so operators
+            can move from commit-reveal to VRF without stranding in-flight bets.
+        @param:
+            randomness_mode: CommitReveal or Vrf
+            vrf_program:     Program owning the randomness accounts, required for Vrf and ignored otherwise
+    */
+    pub fn set_randomness_mode(
+        ctx: Context<SetGlobalPool>,
+        randomness_mode: RandomnessMode,
+        vrf_program: Pubkey,
     ) -> Result<()> {
-        ctx.accounts.global_pool.update_authority = new_update_authority;
+        let global_pool = &mut ctx.accounts.global_pool;
+
+        // Slot hashes are only safe for bets small enough to be opted into with slot_hash
+        require!(
+            randomness_mode != RandomnessMode::SlotHash,
+            GameError::SlotHashModeNotGlobal
+        );
+
+        require!(
+            randomness_mode != RandomnessMode::Vrf || vrf_program != Pubkey::default(),
+            GameError::InvalidVrfProgram
+        );
+
+        let effective_at = global_pool.config_effective_at(Clock::get()?.unix_timestamp)?;
+        global_pool.pending_randomness_mode = PendingRandomnessMode {
+            randomness_mode,
+            vrf_program,
+            effective_at,
+        };
+
+        emit!(RandomnessModeQueued {
+            new_mode: randomness_mode,
+            vrf_program,
+            effective_at,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Permissionless crank that applies a queued randomness mode once its timelock has passed.
+    */
+    pub fn apply_randomness_mode(ctx: Context<ApplyPendingConfig>) -> Result<()> {
+        let global_pool = &mut ctx.accounts.global_pool;
+        let pending = global_pool.pending_randomness_mode;
+
+        require!(pending.effective_at != 0, GameError::NoPendingConfig);
+
+        require!(
+            Clock::get()?.unix_timestamp >= pending.effective_at,
+            GameError::ConfigTimelocked
+        );
+
+        // Leaving Vrf keeps the last VRF program, open VRF bets settle against their own copy anyway
+        let old_mode = global_pool.randomness_mode;
+        global_pool.randomness_mode = pending.randomness_mode;
+        if pending.randomness_mode == RandomnessMode::Vrf {
+            global_pool.vrf_program = pending.vrf_program;
+        }
+        global_pool.pending_randomness_mode = PendingRandomnessMode::default();
+
+        emit!(RandomnessModeChanged {
+            old_mode,
+            new_mode: pending.randomness_mode,
+            vrf_program: global_pool.vrf_program,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Update admin drops a queued randomness mode before it applies.
+    */
+    pub fn cancel_randomness_mode(ctx: Context<SetGlobalPool>) -> Result<()> {
+        let global_pool = &mut ctx.accounts.global_pool;
+        let pending = global_pool.pending_randomness_mode;
+
+        require!(pending.effective_at != 0, GameError::NoPendingConfig);
+
+        global_pool.pending_randomness_mode = PendingRandomnessMode::default();
+
+        emit!(RandomnessModeCancelled {
+            mode: pending.randomness_mode,
+            vrf_program: pending.vrf_program,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Emergency stop. Super admin or guardian can pause betting, settlement, withdrawals and LP deposits.
+            The guardian can only add flags, lifting a pause is left to the super admin.
+        @param:
+            pause_flags: Bitmask of PAUSE_BETTING, PAUSE_SETTLEMENT, PAUSE_WITHDRAWALS and PAUSE_LIQUIDITY,
+                         0 to resume
+    */
+    pub fn set_paused(ctx: Context<SetPause>, pause_flags: u8) -> Result<()> {
+        require!(pause_flags & !PAUSE_ALL == 0, GameError::InvalidPauseFlags);
+
+        let global_pool = &mut ctx.accounts.global_pool;
+        let old_flags = global_pool.pause_flags;
+        let new_flags = if ctx.accounts.admin.key() == global_pool.super_admin {
+            pause_flags
+        } else {
+            old_flags | pause_flags
+        };
+        global_pool.pause_flags = new_flags;
+
+        emit!(PauseChanged {
+            admin: ctx.accounts.admin.key(),
+            old_flags,
+            new_flags,
+        });
+        Ok(())
+    }
+
+    pub fn set_guardian(ctx: Context<SetAuthority>, new_guardian: Pubkey) -> Result<()> {
+        let old_guardian = ctx.accounts.global_pool.guardian;
+        ctx.accounts.global_pool.guardian = new_guardian;
+
+        emit!(AuthorityChanged {
+            role: AuthorityRole::Guardian,
+            old_authority: old_guardian,
+            new_authority: new_guardian,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Propose a new super admin. Takes effect once the new key signs accept_super_admin.
+            Proposing Pubkey::default() cancels a pending transfer.
+    */
+    pub fn propose_super_admin(ctx: Context<SetAuthority>, new_super_admin: Pubkey) -> Result<()> {
+        ctx.accounts.global_pool.pending_super_admin = new_super_admin;
+
+        emit!(AuthorityProposed {
+            role: AuthorityRole::SuperAdmin,
+            current_authority: ctx.accounts.global_pool.super_admin,
+            pending_authority: new_super_admin,
+        });
+        Ok(())
+    }
+
+    pub fn accept_super_admin(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let global_pool = &mut ctx.accounts.global_pool;
+        let new_super_admin = ctx.accounts.new_authority.key();
+
+        require!(
+            global_pool.pending_super_admin != Pubkey::default()
+                && global_pool.pending_super_admin == new_super_admin,
+            GameError::NotPendingAuthority
+        );
+
+        let old_super_admin = global_pool.super_admin;
+        global_pool.super_admin = new_super_admin;
+        global_pool.pending_super_admin = Pubkey::default();
+
+        emit!(AuthorityChanged {
+            role: AuthorityRole::SuperAdmin,
+            old_authority: old_super_admin,
+            new_authority: new_super_admin,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Propose a new operation authority. Takes effect once the new key signs accept_operation_authority.
+            Proposing Pubkey::default() cancels a pending transfer.
+    */
+    pub fn propose_operation_authority(ctx: Context<SetAuthority>, new_operation_authority: Pubkey) -> Result<()> {
+        ctx.accounts.global_pool.pending_operation_authority = new_operation_authority;
+
+        emit!(AuthorityProposed {
+            role: AuthorityRole::Operation,
+            current_authority: ctx.accounts.global_pool.operation_authority,
+            pending_authority: new_operation_authority,
+        });
         Ok(())
     }
+
+    pub fn accept_operation_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let global_pool = &mut ctx.accounts.global_pool;
+        let new_operation_authority = ctx.accounts.new_authority.key();
+
+        require!(
+            global_pool.pending_operation_authority != Pubkey::default()
+                && global_pool.pending_operation_authority == new_operation_authority,
+            GameError::NotPendingAuthority
+        );
+
+        let old_operation_authority = global_pool.operation_authority;
+        global_pool.operation_authority = new_operation_authority;
+        global_pool.pending_operation_authority = Pubkey::default();
+
+        emit!(AuthorityChanged {
+            role: AuthorityRole::Operation,
+            old_authority: old_operation_authority,
+            new_authority: new_operation_authority,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Propose a new finance authority. Takes effect once the new key signs accept_finance_authority.
+            Proposing Pubkey::default() cancels a pending transfer.
+    */
+    pub fn propose_finance_authority(ctx: Context<SetAuthority>, new_finance_authority: Pubkey) -> Result<()> {
+        ctx.accounts.global_pool.pending_finance_authority = new_finance_authority;
+
+        emit!(AuthorityProposed {
+            role: AuthorityRole::Finance,
+            current_authority: ctx.accounts.global_pool.finance_authority,
+            pending_authority: new_finance_authority,
+        });
+        Ok(())
+    }
+
+    pub fn accept_finance_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let global_pool = &mut ctx.accounts.global_pool;
+        let new_finance_authority = ctx.accounts.new_authority.key();
+
+        require!(
+            global_pool.pending_finance_authority != Pubkey::default()
+                && global_pool.pending_finance_authority == new_finance_authority,
+            GameError::NotPendingAuthority
+        );
+
+        let old_finance_authority = global_pool.finance_authority;
+        global_pool.finance_authority = new_finance_authority;
+        global_pool.pending_finance_authority = Pubkey::default();
+
+        emit!(AuthorityChanged {
+            role: AuthorityRole::Finance,
+            old_authority: old_finance_authority,
+            new_authority: new_finance_authority,
+        });
+        Ok(())
+    }
+
+    /**
+        @disc: Propose a new update authority. Takes effect once the new key signs accept_update_authority.
+            Proposing Pubkey::default() cancels a pending transfer.
+    */
+    pub fn propose_update_authority(ctx: Context<SetAuthority>, new_update_authority: Pubkey) -> Result<()> {
+        ctx.accounts.global_pool.pending_update_authority = new_update_authority;
+
+        emit!(AuthorityProposed {
+            role: AuthorityRole::Update,
+            current_authority: ctx.accounts.global_pool.update_authority,
+            pending_authority: new_update_authority,
+        });
+        Ok(())
+    }
+
+    pub fn accept_update_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let global_pool = &mut ctx.accounts.global_pool;
+        let new_update_authority = ctx.accounts.new_authority.key();
+
+        require!(
+            global_pool.pending_update_authority != Pubkey::default()
+                && global_pool.pending_update_authority == new_update_authority,
+            GameError::NotPendingAuthority
+        );
+
+        let old_update_authority = global_pool.update_authority;
+        global_pool.update_authority = new_update_authority;
+        global_pool.pending_update_authority = Pubkey::default();
+
+        emit!(AuthorityChanged {
+            role: AuthorityRole::Update,
+            old_authority: old_update_authority,
+            new_authority: new_update_authority,
+        });
+        Ok(())
+    }
+}
+
+fn play_sol_game(ctx: Context<PlayGame>, params: InitPlayGameParams, roll_count: u8) -> Result<()> {
+    let total_stake = open_sol_bet(
+        &mut ctx.accounts.player_pool,
+        &mut ctx.accounts.global_authority,
+        &params,
+        roll_count,
+        ctx.accounts.owner.key(),
+        ctx.accounts.casino_vault.lamports(),
+        ctx.accounts.vrf_randomness.as_ref(),
+    )?;
+
+    require!(
+        ctx.accounts.owner.to_account_info().lamports() > total_stake,
+        GameError::InsufficientUserBalance
+    );
+
+    // Transfer the stake of every roll to this PDA from User Wallet
+    sol_transfer_user(
+        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.game_vault.to_account_info(),
+        ctx.accounts.system_program.to_account_info(),
+        total_stake,
+    )?;
+
+    msg!(
+        "User's choice is {:?} {}",
+        params.game_kind,
+        params.target_number
+    );
+
+    Ok(())
+}
+
+// Validate a SOL bet and record it on a fresh player pool, returning the total stake.
+// The caller moves the stake, from the player's wallet or their PlayerBalance, and marks
+// the pool funded_from_balance beforehand in the latter case.
+fn open_sol_bet(
+    player_pool: &mut Account<PlayerPool>,
+    global_authority: &mut GlobalPool,
+    params: &InitPlayGameParams,
+    roll_count: u8,
+    player: Pubkey,
+    casino_vault_balance: u64,
+    vrf_randomness: Option<&UncheckedAccount>,
+) -> Result<u64> {
+    let target_number = params.target_number;
+    let bet_amount = params.bet_amount;
+    let total_stake = bet_amount
+        .checked_mul(roll_count as u64)
+        .ok_or(GameError::MathOverflow)?;
+
+    require!(
+        !global_authority.is_paused(PAUSE_BETTING),
+        GameError::Paused
+    );
+
+    require!(
+        !params.slot_hash || total_stake <= global_authority.slot_hash_max_bet,
+        GameError::SlotHashBetTooLarge
+    );
+
+    let max_win_amount = global_authority.effective_max_win(
+        global_authority.max_win_amount,
+        casino_vault_balance,
+    )?;
+
+    let (multiplier, payout, net_win) = check_bet(
+        global_authority,
+        global_authority.min_bet_amount,
+        max_win_amount,
+        params,
+        roll_count,
+    )?;
+
+    // A balance funded stake sits in the casino vault until settlement pays it back,
+    // so it is owed like the winnings and counts toward the exposure limit
+    let potential_win = if player_pool.funded_from_balance {
+        net_win
+            .checked_add(total_stake)
+            .ok_or(GameError::MathOverflow)?
+    } else {
+        net_win
+    };
+
+    // require!(
+    //     (bet_amount * 2 * (global_authority.rtp / 100) - bet_amount)
+    //         < global_authority.max_win_amount,
+    //     GameError::InvalidBetAmountMaxWinAmountViolation
+    // );
+
+    require!(
+        casino_vault_balance > total_stake,
+        GameError::InsufficientCasinoVault
+    );
+
+    global_authority.outstanding_liability = check_exposure(
+        global_authority.outstanding_liability,
+        potential_win,
+        casino_vault_balance,
+        global_authority.max_liability_bps,
+    )?;
+
+    player_pool.status = GameStatus::Active;
+    player_pool.bet = bet_amount;
+    player_pool.roll_count = roll_count;
+    player_pool.game_kind = params.game_kind;
+    player_pool.target_num = target_number;
+    player_pool.range_end = params.range_end;
+    player_pool.is_under = params.is_under;
+    player_pool.player = player;
+    player_pool.server_seed_hash = params.server_seed_hash;
+    player_pool.client_seed = params.client_seed;
+    player_pool.mint = Pubkey::default();
+    player_pool.potential_win = potential_win;
+    player_pool.rtp = global_authority.rtp;
+    player_pool.multiplier = multiplier;
+    player_pool.payout = payout;
+    let clock = Clock::get()?;
+    player_pool.bet_slot = clock.slot;
+    player_pool.bet_timestamp = clock.unix_timestamp;
+    player_pool.randomness_mode = if params.slot_hash {
+        RandomnessMode::SlotHash
+    } else {
+        global_authority.randomness_mode
+    };
+
+    if player_pool.randomness_mode == RandomnessMode::Vrf {
+        // The request account must not exist yet, so its output can't be known before the bet
+        let vrf_randomness = vrf_randomness.ok_or(GameError::InvalidRandomnessAccount)?;
+
+        require!(
+            vrf_randomness.key()
+                == VrfRandomness::address(&player_pool.key(), &global_authority.vrf_program),
+            GameError::InvalidRandomnessAccount
+        );
+
+        require!(
+            vrf_randomness.data_is_empty(),
+            GameError::InvalidRandomnessAccount
+        );
+
+        player_pool.vrf_randomness = vrf_randomness.key();
+        player_pool.vrf_program = global_authority.vrf_program;
+    }
+
+    emit!(BetPlaced {
+        player,
+        game_session_id: params.game_session_id,
+        mint: Pubkey::default(),
+        bet_amount,
+        roll_count,
+        game_kind: params.game_kind,
+        target_num: target_number,
+        range_end: params.range_end,
+        is_under: params.is_under,
+        multiplier,
+        server_seed_hash: params.server_seed_hash,
+    });
+
+    Ok(total_stake)
 }
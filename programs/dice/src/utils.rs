@@ -1,10 +1,22 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::{program::invoke, system_instruction::transfer};
-// use sha2::Digest;
-// use sha2::Sha256;
+use anchor_spl::token::{self, Burn, MintTo, Transfer};
+use sha2::Digest;
+use sha2::Sha256;
 use solana_program::program::invoke_signed;
 use std::cmp::Ordering;
 
+use crate::account::{
+    ConfigField, GameKind, GameStatus, GlobalPool, InitPlayGameParams, PendingConfig,
+    PlayerBalance, PlayerPool,
+};
+use crate::constants::{
+    BPS_DENOMINATOR, MAX_ROLLS, MULTIPLIER_PRECISION, ROLL_RANGE, RTP_DENOMINATOR,
+    VAULT_AUTHORITY_SEED,
+};
+use crate::error::GameError;
+use crate::event::{BetSettled, ConfigQueued};
+
 // transfer sol
 pub fn sol_transfer_with_signer<'a>(
     source: AccountInfo<'a>,
@@ -29,14 +41,99 @@ pub fn sol_transfer_user<'a>(
     Ok(())
 }
 
-pub fn puffed_out_string(s: &String, size: usize) -> String {
+// Move lamports out of an account owned by this program, such as an auto-bet balance.
+// The system program can't debit these, so the balances are adjusted directly.
+pub fn program_account_transfer<'a>(
+    source: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    amount: u64,
+) -> Result<()> {
+    source.sub_lamports(amount)?;
+    destination.add_lamports(amount)?;
+    Ok(())
+}
+
+// transfer spl token
+pub fn token_transfer_with_signer<'a>(
+    source: AccountInfo<'a>,
+    destination: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    signers: &[&[&[u8]]; 1],
+    amount: u64,
+) -> Result<()> {
+    let cpi_accounts = Transfer {
+        from: source,
+        to: destination,
+        authority,
+    };
+    token::transfer(
+        CpiContext::new_with_signer(token_program, cpi_accounts, signers),
+        amount,
+    )
+}
+
+pub fn token_transfer_user<'a>(
+    source: AccountInfo<'a>,
+    destination: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    amount: u64,
+) -> Result<()> {
+    let cpi_accounts = Transfer {
+        from: source,
+        to: destination,
+        authority,
+    };
+    token::transfer(CpiContext::new(token_program, cpi_accounts), amount)
+}
+
+pub fn token_mint_with_signer<'a>(
+    mint: AccountInfo<'a>,
+    destination: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    signers: &[&[&[u8]]; 1],
+    amount: u64,
+) -> Result<()> {
+    let cpi_accounts = MintTo {
+        mint,
+        to: destination,
+        authority,
+    };
+    token::mint_to(
+        CpiContext::new_with_signer(token_program, cpi_accounts, signers),
+        amount,
+    )
+}
+
+pub fn token_burn_with_signer<'a>(
+    mint: AccountInfo<'a>,
+    source: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    token_program: AccountInfo<'a>,
+    signers: &[&[&[u8]]; 1],
+    amount: u64,
+) -> Result<()> {
+    let cpi_accounts = Burn {
+        mint,
+        from: source,
+        authority,
+    };
+    token::burn(
+        CpiContext::new_with_signer(token_program, cpi_accounts, signers),
+        amount,
+    )
+}
+
+pub fn puffed_out_string(s: &str, size: usize) -> String {
     let mut array_of_zeroes = vec![];
 
     let puff_amount = size - s.len();
     while array_of_zeroes.len() < puff_amount {
         array_of_zeroes.push(0u8);
     }
-    s.clone() + std::str::from_utf8(&array_of_zeroes).unwrap()
+    s.to_owned() + std::str::from_utf8(&array_of_zeroes).unwrap()
 }
 
 //  Generate pseudo random number
@@ -45,28 +142,376 @@ pub struct HashStruct {
     pub initial_seed: u64,
 }
 
-// unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
-//     ::std::slice::from_raw_parts((p as *const T) as *const u8, ::std::mem::size_of::<T>())
-// }
+// Commitment stored at bet time for the operator's server seed
+pub fn hash_server_seed(server_seed: &[u8; 32]) -> [u8; 32] {
+    Sha256::digest(server_seed).into()
+}
+
+// Seed of a commit-reveal roll: the server seed mixed with the hash of the first slot after the bet.
+// That slot doesn't exist when the operator co-signs, so it can't know the roll of the bet it signs.
+pub fn mix_slot_hash(server_seed: &[u8; 32], slot_hash: &[u8; 32]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(server_seed)
+        .chain_update(slot_hash)
+        .finalize()
+        .into()
+}
+
+// Derive the 0..99 roll from the revealed server seed and the player's client seed
+pub fn compute_roll(server_seed: &[u8; 32], client_seed: &[u8; 32], game_session_id: u64) -> u8 {
+    compute_roll_at(server_seed, client_seed, game_session_id, 0)
+}
+
+// Roll `index` of a play_multi bet. Later rolls append the index to the hash input,
+// so roll 0 is exactly compute_roll and single bets are unaffected.
+pub fn compute_roll_at(
+    server_seed: &[u8; 32],
+    client_seed: &[u8; 32],
+    game_session_id: u64,
+    index: u8,
+) -> u8 {
+    let mut hasher = Sha256::new()
+        .chain_update(server_seed)
+        .chain_update(client_seed)
+        .chain_update(game_session_id.to_be_bytes());
+    if index > 0 {
+        hasher.update([index]);
+    }
+    let hash = hasher.finalize();
+
+    let random = u64::from_le_bytes(hash[..8].try_into().expect("slice with incorrect length"));
+
+    (random % ROLL_RANGE) as u8
+}
+
+// Number of rolls in 0..ROLL_RANGE that win for the given target
+pub fn win_outcomes(target_num: u8, is_under: bool) -> u64 {
+    if is_under {
+        target_num as u64
+    } else {
+        (ROLL_RANGE - 1).saturating_sub(target_num as u64)
+    }
+}
+
+// Payout multiplier scaled by MULTIPLIER_PRECISION: (ROLL_RANGE / win_outcomes) * rtp%
+pub fn calc_multiplier(rtp: u64, win_outcomes: u64) -> Result<u64> {
+    require!(win_outcomes > 0, GameError::InvalidTargetNumber);
+
+    let multiplier = (rtp as u128)
+        .checked_mul(ROLL_RANGE as u128)
+        .and_then(|v| v.checked_mul(MULTIPLIER_PRECISION as u128))
+        .and_then(|v| v.checked_div(RTP_DENOMINATOR as u128 * win_outcomes as u128))
+        .ok_or(GameError::MathOverflow)?;
+
+    u64::try_from(multiplier).map_err(|_| error!(GameError::MathOverflow))
+}
+
+// Total amount returned to the player on a win, stake included. Rounds down.
+pub fn calc_payout(bet_amount: u64, multiplier: u64) -> Result<u64> {
+    let payout = (bet_amount as u128)
+        .checked_mul(multiplier as u128)
+        .ok_or(GameError::MathOverflow)?
+        / MULTIPLIER_PRECISION as u128;
+
+    u64::try_from(payout).map_err(|_| error!(GameError::MathOverflow))
+}
+
+// Validate a bet against the table limits and return its payout multiplier and potential net win
+pub fn check_bet(
+    global_authority: &GlobalPool,
+    min_bet_amount: u64,
+    max_win_amount: u64,
+    params: &InitPlayGameParams,
+    roll_count: u8,
+) -> Result<(u64, u64, u64)> {
+    let bet_amount = params.bet_amount;
+    let target_number = params.target_number;
+
+    require!(
+        roll_count >= 1 && roll_count as usize <= MAX_ROLLS,
+        GameError::InvalidRollCount
+    );
+
+    require!(
+        min_bet_amount <= bet_amount,
+        GameError::InvalidBetAmount
+    );
+
+    // min_num and max_num bound the over/under target, other games validate their own shape
+    if params.game_kind == GameKind::OverUnder {
+        require!(
+            global_authority.min_num < target_number,
+            GameError::InvalidTargetNumber
+        );
+
+        require!(
+            global_authority.max_num > target_number,
+            GameError::InvalidTargetNumber
+        );
+    }
+
+    let outcomes = params
+        .game_kind
+        .win_outcomes(target_number, params.range_end, params.is_under)?;
+    let multiplier = calc_multiplier(global_authority.rtp, outcomes)?;
+
+    // Range and Exact skip min_num/max_num, so a win paying back less than the stake is
+    // caught here rather than by the target bounds
+    require!(
+        multiplier > MULTIPLIER_PRECISION,
+        GameError::InvalidTargetNumber
+    );
+
+    let payout = calc_payout(bet_amount, multiplier)?;
+
+    // max_win_amount caps the combined gain of every roll winning
+    let net_gain = payout
+        .saturating_sub(bet_amount)
+        .checked_mul(roll_count as u64)
+        .ok_or(GameError::MathOverflow)?;
+
+    require!(
+        net_gain < max_win_amount,
+        GameError::InvalidBetAmountMaxWinAmountViolation
+    );
+
+    Ok((multiplier, payout, net_gain))
+}
+
+// Add a bet's potential win to the outstanding liability, rejecting it if the total
+// would exceed max_liability_bps of the vault balance. Returns the new liability.
+pub fn check_exposure(
+    outstanding_liability: u64,
+    potential_win: u64,
+    vault_balance: u64,
+    max_liability_bps: u64,
+) -> Result<u64> {
+    let new_liability = outstanding_liability
+        .checked_add(potential_win)
+        .ok_or(GameError::MathOverflow)?;
+
+    let max_liability = (vault_balance as u128)
+        .checked_mul(max_liability_bps as u128)
+        .ok_or(GameError::MathOverflow)?
+        / BPS_DENOMINATOR as u128;
+
+    require!(
+        new_liability as u128 <= max_liability,
+        GameError::ExposureLimitExceeded
+    );
+
+    Ok(new_liability)
+}
+
+// Queue a config change behind the timelock so players get notice before it applies
+pub fn queue_config_change(
+    global_pool: &mut GlobalPool,
+    field: ConfigField,
+    value: u64,
+) -> Result<()> {
+    global_pool.check_config(field, value)?;
+
+    let effective_at = global_pool.config_effective_at(Clock::get()?.unix_timestamp)?;
+
+    global_pool.pending_configs[field as usize] = PendingConfig {
+        value,
+        effective_at,
+    };
+
+    emit!(ConfigQueued {
+        field,
+        value,
+        effective_at,
+    });
+
+    Ok(())
+}
+
+// Hash of the earliest slot after `after_slot` in raw SlotHashes sysvar data.
+// Entries are (slot, hash) pairs, newest first, behind a u64 length prefix. Returns None
+// until such a slot exists, and again once `after_slot` itself has left the window, since
+// the oldest remaining entry would then no longer be the slot right after the bet.
+pub fn find_slot_hash(data: &[u8], after_slot: u64) -> Option<[u8; 32]> {
+    const ENTRY_SIZE: usize = 8 + 32;
+
+    let len = u64::from_le_bytes(data.get(..8)?.try_into().ok()?) as usize;
+    let mut next_hash = None;
+
+    for entry in data.get(8..)?.chunks_exact(ENTRY_SIZE).take(len) {
+        let slot = u64::from_le_bytes(entry[..8].try_into().ok()?);
+        if slot <= after_slot {
+            return next_hash;
+        }
+
+        next_hash = Some(entry[8..].try_into().ok()?);
+    }
+
+    None
+}
+
+// Accounts moved by a SOL bet settlement, shared by every randomness source
+pub struct SolSettlement<'a, 'info> {
+    pub owner: AccountInfo<'info>,
+    pub operator: AccountInfo<'info>,
+    pub game_vault: AccountInfo<'info>,
+    pub casino_vault: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+    pub game_vault_seeds: &'a [&'a [u8]],
+    pub casino_bump: u8,
+    pub player_balance: Option<&'a mut Account<'info, PlayerBalance>>,
+}
+
+// Pay out or collect a SOL bet whose rolls have already been recorded on the player pool.
+// Every winning roll pays the per-roll payout, settled against the stake of all rolls.
+pub fn settle_sol_bet(
+    player_pool: &mut PlayerPool,
+    global_authority: &mut GlobalPool,
+    accounts: SolSettlement,
+    game_session_id: u64,
+) -> Result<()> {
+    let rolls = player_pool.rolled().to_vec();
+    let wins = rolls
+        .iter()
+        .filter(|roll| player_pool.is_winning_roll(**roll))
+        .count() as u64;
+    let is_win = wins > 0;
+
+    msg!(
+        "Rolled {:?} on {:?} -> {} of {} won",
+        rolls,
+        player_pool.game_kind,
+        wins,
+        rolls.len()
+    );
+
+    // Settle on the terms locked in at bet time, not the current config
+    let multiplier = player_pool.multiplier;
+    let payout = player_pool
+        .payout
+        .checked_mul(wins)
+        .ok_or(GameError::MathOverflow)?;
+
+    global_authority.outstanding_liability = global_authority
+        .outstanding_liability
+        .saturating_sub(player_pool.potential_win);
+
+    emit!(BetSettled {
+        player: player_pool.player,
+        game_session_id,
+        mint: Pubkey::default(),
+        game_kind: player_pool.game_kind,
+        target_num: player_pool.target_num,
+        range_end: player_pool.range_end,
+        is_under: player_pool.is_under,
+        roll: player_pool.roll,
+        rolls,
+        wins: wins as u8,
+        is_win,
+        multiplier,
+        payout,
+    });
+
+    pay_sol_bet(player_pool, accounts, payout)
+}
+
+// Move the funds of a SOL bet paying `payout` in total, nothing for a loss, and record the outcome
+pub fn pay_sol_bet(
+    player_pool: &mut PlayerPool,
+    accounts: SolSettlement,
+    payout: u64,
+) -> Result<()> {
+    let stake = player_pool.total_stake()?;
+    let is_win = payout > 0;
+
+    // A balance funded stake already sits in the casino vault, so a win pays the whole
+    // payout back into the player balance and a loss moves nothing
+    if player_pool.funded_from_balance {
+        let player_balance = accounts
+            .player_balance
+            .ok_or(GameError::MissingPlayerBalance)?;
+
+        if is_win {
+            sol_transfer_with_signer(
+                accounts.casino_vault.clone(),
+                player_balance.to_account_info(),
+                accounts.system_program.clone(),
+                &[&[VAULT_AUTHORITY_SEED.as_bytes(), &[accounts.casino_bump]]],
+                payout,
+            )?;
+            player_balance.balance = player_balance
+                .balance
+                .checked_add(payout)
+                .ok_or(GameError::MathOverflow)?;
+        }
+
+        player_pool.status = if is_win {
+            GameStatus::Win
+        } else {
+            GameStatus::Lose
+        };
+        return Ok(());
+    }
+
+    // Anything sitting in the game vault besides the stake goes back to the operator with
+    // the player pool rent. Swept first so the stake transfers below leave the vault empty
+    // rather than below rent exemption.
+    let residue = accounts.game_vault.lamports().saturating_sub(stake);
+    if residue > 0 {
+        sol_transfer_with_signer(
+            accounts.game_vault.clone(),
+            accounts.operator.clone(),
+            accounts.system_program.clone(),
+            &[accounts.game_vault_seeds],
+            residue,
+        )?;
+    }
+
+    if is_win {
+        // The casino bank pays the winnings on top of the stake
+        if payout > stake {
+            sol_transfer_with_signer(
+                accounts.casino_vault.clone(),
+                accounts.owner.clone(),
+                accounts.system_program.clone(),
+                &[&[VAULT_AUTHORITY_SEED.as_bytes(), &[accounts.casino_bump]]],
+                payout - stake,
+            )?;
+        }
+
+        // Stake left over from a sub-1x multiplier stays with the house
+        if stake > payout {
+            sol_transfer_with_signer(
+                accounts.game_vault.clone(),
+                accounts.casino_vault.clone(),
+                accounts.system_program.clone(),
+                &[accounts.game_vault_seeds],
+                stake - payout,
+            )?;
+        }
+
+        sol_transfer_with_signer(
+            accounts.game_vault.clone(),
+            accounts.owner.clone(),
+            accounts.system_program.clone(),
+            &[accounts.game_vault_seeds],
+            payout.min(stake),
+        )?;
 
-// pub fn get_rand(seed: u64, nonce: u64) -> u64 {
-//     let hashstruct = HashStruct {
-//         nonce,
-//         initial_seed: seed,
-//     };
-//     let vec_to_hash = unsafe { self::any_as_u8_slice(&hashstruct) };
-//     let hash = &(Sha256::new().chain_update(vec_to_hash).finalize()[..32]);
+        player_pool.status = GameStatus::Win;
+    } else {
+        sol_transfer_with_signer(
+            accounts.game_vault.clone(),
+            accounts.casino_vault.clone(),
+            accounts.system_program.clone(),
+            &[accounts.game_vault_seeds],
+            stake,
+        )?;
 
-//     // hash is a vector of 32 8bit numbers.  We can take slices of this to generate our 4 random u64s
-//     let mut hashed_randoms: [u64; 4] = [0; 4];
-//     for i in 0..4 {
-//         let hash_slice = &hash[i * 8..(i + 1) * 8];
-//         hashed_randoms[i] =
-//             u64::from_le_bytes(hash_slice.try_into().expect("slice with incorrect length"));
-//     }
+        player_pool.status = GameStatus::Lose;
+    }
 
-//     return hashed_randoms[2] % 1000000;
-// }
+    Ok(())
+}
 
 pub fn resize_account<'info>(
     account_info: AccountInfo<'info>,
@@ -99,3 +544,42 @@ pub fn resize_account<'info>(
     account_info.realloc(new_space, false)?;
     Ok(())
 }
+
+// SOL in the casino vault that belongs to LP shares: whatever sits above `floor`. Exits use the
+// full reserve floor so what open bets can still win is never paid out for shares, deposits and
+// the initial house shares only the min reserve.
+pub fn lp_equity(vault_balance: u64, floor: u64) -> u64 {
+    vault_balance.saturating_sub(floor)
+}
+
+// amount * numerator / denominator in u128, rounded down or up
+fn mul_div(amount: u64, numerator: u64, denominator: u64, round_up: bool) -> Result<u64> {
+    require!(denominator > 0, GameError::PoolInsolvent);
+
+    let product = (amount as u128) * (numerator as u128);
+    let result = if round_up {
+        product.div_ceil(denominator as u128)
+    } else {
+        product / denominator as u128
+    };
+
+    u64::try_from(result).map_err(|_| error!(GameError::MathOverflow))
+}
+
+// Shares minted for a deposit, 1:1 into an empty pool, otherwise at the current share price
+pub fn shares_for_deposit(amount: u64, supply: u64, equity: u64) -> Result<u64> {
+    if supply == 0 {
+        return Ok(amount);
+    }
+    mul_div(amount, supply, equity, false)
+}
+
+// SOL paid out for burning shares, rounded in the pool's favour
+pub fn sol_for_shares(shares: u64, supply: u64, equity: u64) -> Result<u64> {
+    mul_div(shares, equity, supply, false)
+}
+
+// Shares that must be burned to take amount out of the pool, rounded in the pool's favour
+pub fn shares_for_sol(amount: u64, supply: u64, equity: u64) -> Result<u64> {
+    mul_div(amount, supply, equity, true)
+}
@@ -0,0 +1,79 @@
+use anchor_lang::prelude::*;
+
+declare_id!("BRmT4yxrit8aG6Ygwh7Guo6ncU5riuFrnZMmnQqu3TqY");
+
+// Same seed the dice program derives request accounts with
+pub const RANDOMNESS_SEED: &str = "orao-vrf-randomness-request";
+
+/**
+    Minimal stand-in for an ORAO-style VRF oracle, only meant for localnet tests.
+    Anyone can fulfill a request with arbitrary bytes, where a real oracle submits a proof.
+*/
+#[program]
+pub mod mock_vrf {
+    use super::*;
+
+    pub fn request(ctx: Context<Request>, seed: [u8; 32]) -> Result<()> {
+        let randomness = &mut ctx.accounts.randomness;
+        randomness.seed = seed;
+        randomness.randomness = [0u8; 64];
+
+        Ok(())
+    }
+
+    pub fn fulfill(ctx: Context<Fulfill>, randomness: [u8; 64]) -> Result<()> {
+        require!(
+            ctx.accounts.randomness.randomness == [0u8; 64],
+            MockVrfError::AlreadyFulfilled
+        );
+        require!(randomness != [0u8; 64], MockVrfError::EmptyRandomness);
+
+        ctx.accounts.randomness.randomness = randomness;
+
+        Ok(())
+    }
+}
+
+#[account]
+pub struct Randomness {
+    pub seed: [u8; 32],       // 32
+    pub randomness: [u8; 64], // 64, all zero until fulfilled
+}
+
+impl Randomness {
+    pub const DATA_SIZE: usize = 32 + 64; // 96
+}
+
+#[derive(Accounts)]
+#[instruction(seed: [u8; 32])]
+pub struct Request<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        space = 8 + Randomness::DATA_SIZE,
+        seeds = [RANDOMNESS_SEED.as_bytes(), &seed],
+        bump,
+        payer = payer
+    )]
+    pub randomness: Account<'info, Randomness>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Fulfill<'info> {
+    pub fulfiller: Signer<'info>,
+
+    #[account(mut)]
+    pub randomness: Account<'info, Randomness>,
+}
+
+#[error_code]
+pub enum MockVrfError {
+    #[msg("Randomness already fulfilled")] // 6000
+    AlreadyFulfilled,
+    #[msg("Randomness must not be all zero")] // 6001
+    EmptyRandomness,
+}
@@ -1,6 +1,7 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { Dice } from "../target/types/dice";
+import { MockVrf } from "../target/types/mock_vrf";
 import {
   PublicKey,
   SystemProgram,
@@ -9,9 +10,19 @@ import {
   LAMPORTS_PER_SOL,
   sendAndConfirmTransaction,
   Transaction,
+  SYSVAR_SLOT_HASHES_PUBKEY,
 } from "@solana/web3.js";
 import { expect } from "chai";
 import { bs58 } from "@coral-xyz/anchor/dist/cjs/utils/bytes";
+import { createHash, randomBytes } from "crypto";
+import {
+  createMint,
+  getAccount,
+  getMint,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+  transfer,
+} from "@solana/spl-token";
 
 let cluster = "devnet";
 
@@ -20,6 +31,7 @@ const provider = anchor.AnchorProvider.env();
 anchor.setProvider(provider);
 
 const program = anchor.workspace.Dice as Program<Dice>;
+const mockVrf = anchor.workspace.MockVrf as Program<MockVrf>;
 // const admin = provider.wallet;
 
 const superAdmin = Keypair.fromSecretKey(
@@ -66,6 +78,24 @@ const updateAdmin = Keypair.fromSecretKey(
 const newAdmin = Keypair.generate();
 const game_session_id = new anchor.BN(1);
 const second_game_session_id = new anchor.BN(2);
+const token_game_session_id = new anchor.BN(3);
+const refund_game_session_id = new anchor.BN(4);
+const vrf_game_session_id = new anchor.BN(5);
+const slot_hash_game_session_id = new anchor.BN(6);
+const coin_flip_game_session_id = new anchor.BN(7);
+const multi_game_session_id = new anchor.BN(8);
+const balance_game_session_id = new anchor.BN(9);
+const session_key_game_session_id = new anchor.BN(10);
+const dynamic_max_win_game_session_id = new anchor.BN(11);
+const forfeit_game_session_id = new anchor.BN(12);
+const lp_game_session_id = new anchor.BN(13);
+
+// Operator server seeds are committed at bet time and revealed at settlement,
+// mixed with the hash of the first slot after the bet
+const CONFIG_TIMELOCK = 2; // seconds, set at initialize so the suite can wait changes out
+const clientSeed = randomBytes(32);
+const serverSeed = randomBytes(32);
+const secondServerSeed = randomBytes(32);
 
 const connection =
   cluster == "localnet"
@@ -144,7 +174,8 @@ describe("Dice Game", () => {
       .initialize(
         operationAdmin.publicKey,
         financialAdmin.publicKey,
-        updateAdmin.publicKey
+        updateAdmin.publicKey,
+        new anchor.BN(CONFIG_TIMELOCK)
       )
       .accounts({
         admin: superAdmin.publicKey,
@@ -173,6 +204,50 @@ describe("Dice Game", () => {
     console.log(`Casino vault deposit sig => https://solscan.io/${sig}`);
   });
 
+  it("should hold config changes behind the timelock", async () => {
+    await program.methods
+      .setRtp(new anchor.BN(95))
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+
+    try {
+      await program.methods
+        .applyPendingConfig({ rtp: {} })
+        .accounts({ globalPool: globalAuthorityPDA })
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("ConfigTimelocked");
+    }
+
+    await program.methods
+      .cancelPendingConfig({ rtp: {} })
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+
+    let globalAuthority = await program.account.globalPool.fetch(globalAuthorityPDA);
+    expect(globalAuthority.pendingConfigs[0].effectiveAt.toNumber()).to.equal(0);
+
+    // The suite runs on the short timelock it was initialized with
+    expect(globalAuthority.configTimelock.toNumber()).to.equal(CONFIG_TIMELOCK);
+
+    // but set_config_timelock can't go under the one hour floor
+    for (const timelock of [0, 60 * 60 - 1]) {
+      try {
+        await program.methods
+          .setConfigTimelock(new anchor.BN(timelock))
+          .accounts({ admin: superAdmin.publicKey, globalPool: globalAuthorityPDA })
+          .signers([superAdmin])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err) {
+        expect(err.toString()).to.contain("InvalidConfigTimelock");
+      }
+    }
+  });
+
   it("should allow a player to place a bet", async () => {
     const betAmount = new anchor.BN(0.2 * LAMPORTS_PER_SOL); // 0.1 SOL
     const targetNumber = 57;
@@ -181,7 +256,17 @@ describe("Dice Game", () => {
     console.log("betAmount: ", betAmount);
 
     const tx = await program.methods
-      .playGame(targetNumber, is_under, betAmount, game_session_id)
+      .playGame({
+        gameKind: { overUnder: {} },
+        targetNumber,
+        rangeEnd: 0,
+        isUnder: is_under,
+        betAmount,
+        gameSessionId: game_session_id,
+        serverSeedHash: sha256(serverSeed),
+        clientSeed: [...clientSeed],
+        slotHash: false,
+      })
       .accounts({
         owner: player.publicKey,
         operator: operationAdmin.publicKey,
@@ -225,7 +310,17 @@ describe("Dice Game", () => {
 
     try {
       const tx = await program.methods
-        .playGame(57, true, betAmount, second_game_session_id)
+        .playGame({
+          gameKind: { overUnder: {} },
+          targetNumber: 57,
+          rangeEnd: 0,
+          isUnder: true,
+          betAmount,
+          gameSessionId: second_game_session_id,
+          serverSeedHash: sha256(secondServerSeed),
+          clientSeed: [...clientSeed],
+          slotHash: false,
+        })
         .accounts({
           owner: secondPlayer.publicKey,
           operator: operationAdmin.publicKey,
@@ -251,12 +346,341 @@ describe("Dice Game", () => {
     }
   });
 
-  it("should process a win correctly", async () => {
-    const initialBalance = await connection.getBalance(gameVaultPDA);
-    console.log("Initial Game Balance before win was {}", initialBalance);
+  it("should settle a coin flip with its own payout and win check", async () => {
+    const [flipPlayerPoolPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("player-pool"), coin_flip_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const [flipGameVaultPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("vault-authority"), coin_flip_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const flipServerSeed = randomBytes(32);
+
+    await program.methods
+      .playGame({
+        gameKind: { coinFlip: {} },
+        targetNumber: 1,
+        rangeEnd: 0,
+        isUnder: false,
+        betAmount: new anchor.BN(0.2 * LAMPORTS_PER_SOL),
+        gameSessionId: coin_flip_game_session_id,
+        serverSeedHash: sha256(flipServerSeed),
+        clientSeed: [...clientSeed],
+        slotHash: false,
+      })
+      .accounts({
+        owner: player.publicKey,
+        operator: operationAdmin.publicKey,
+        playerPool: flipPlayerPoolPDA,
+        gameVault: flipGameVaultPDA,
+      })
+      .signers([player, operationAdmin])
+      .rpc();
+
+    const flipPool = await program.account.playerPool.fetch(flipPlayerPoolPDA);
+    // 50 winning rolls out of 100 at 95% rtp
+    expect(flipPool.multiplier.toNumber()).to.equal(1_900_000_000);
+
+    const flipSlotHash = await slotHashAfter(flipPool.betSlot);
+    const sig = await program.methods
+      .setResult({ gameSessionId: coin_flip_game_session_id, serverSeed: [...flipServerSeed] })
+      .accounts({
+        owner: player.publicKey,
+        operator: operationAdmin.publicKey,
+        gameVault: flipGameVaultPDA,
+        playerPool: flipPlayerPoolPDA,
+      })
+      .signers([operationAdmin])
+      .rpc({ commitment: "confirmed" });
+
+    const settled = (await getEvents(sig)).find((e) => e.name === "betSettled");
+    const roll = computeRoll(mixSlotHash(flipServerSeed, flipSlotHash), clientSeed, coin_flip_game_session_id);
+    expect(settled.data.roll).to.equal(roll);
+    expect(settled.data.isWin).to.equal(roll % 2 === 1);
+  });
+
+  it("should reject a range bet covering every roll or paying under 1x", async () => {
+    const [rangePlayerPoolPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("player-pool"), coin_flip_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const [rangeGameVaultPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("vault-authority"), coin_flip_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+
+    // 0..=98 is 99 of 100 rolls, under 1x once the house edge is taken
+    for (const rangeEnd of [99, 98]) {
+      try {
+        await program.methods
+          .playGame({
+            gameKind: { range: {} },
+            targetNumber: 0,
+            rangeEnd,
+            isUnder: false,
+            betAmount: new anchor.BN(0.2 * LAMPORTS_PER_SOL),
+            gameSessionId: coin_flip_game_session_id,
+            serverSeedHash: sha256(randomBytes(32)),
+            clientSeed: [...clientSeed],
+            slotHash: false,
+          })
+          .accounts({
+            owner: player.publicKey,
+            operator: operationAdmin.publicKey,
+            playerPool: rangePlayerPoolPDA,
+            gameVault: rangeGameVaultPDA,
+          })
+          .signers([player, operationAdmin])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err) {
+        expect(err.toString()).to.contain("InvalidTargetNumber");
+      }
+    }
+  });
+
+  it("should resolve every roll of a multi-roll bet in one settlement", async () => {
+    const [multiPlayerPoolPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("player-pool"), multi_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const [multiGameVaultPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("vault-authority"), multi_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const multiServerSeed = randomBytes(32);
+    const rollCount = 3;
+    const betAmount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+
+    await program.methods
+      .playMulti(
+        {
+          gameKind: { overUnder: {} },
+          targetNumber: 57,
+          rangeEnd: 0,
+          isUnder: true,
+          betAmount,
+          gameSessionId: multi_game_session_id,
+          serverSeedHash: sha256(multiServerSeed),
+          clientSeed: [...clientSeed],
+          slotHash: false,
+        },
+        rollCount
+      )
+      .accounts({
+        owner: player.publicKey,
+        operator: operationAdmin.publicKey,
+        playerPool: multiPlayerPoolPDA,
+        gameVault: multiGameVaultPDA,
+      })
+      .signers([player, operationAdmin])
+      .rpc();
+
+    const multiPool = await program.account.playerPool.fetch(multiPlayerPoolPDA);
+    expect(multiPool.rollCount).to.equal(rollCount);
+    expect(await connection.getBalance(multiGameVaultPDA)).to.be.at.least(
+      betAmount.toNumber() * rollCount
+    );
+
+    const multiSlotHash = await slotHashAfter(multiPool.betSlot);
+    const playerBalanceBefore = await connection.getBalance(player.publicKey);
+    const sig = await program.methods
+      .setResult({ gameSessionId: multi_game_session_id, serverSeed: [...multiServerSeed] })
+      .accounts({
+        owner: player.publicKey,
+        operator: operationAdmin.publicKey,
+        gameVault: multiGameVaultPDA,
+        playerPool: multiPlayerPoolPDA,
+      })
+      .signers([operationAdmin])
+      .rpc({ commitment: "confirmed" });
+
+    const settled = (await getEvents(sig)).find((e) => e.name === "betSettled");
+    const rolls = [...Array(rollCount).keys()].map((i) =>
+      computeRoll(mixSlotHash(multiServerSeed, multiSlotHash), clientSeed, multi_game_session_id, i)
+    );
+    const wins = rolls.filter((roll) => roll < 57).length;
+    expect([...settled.data.rolls]).to.deep.equal(rolls);
+    expect(settled.data.wins).to.equal(wins);
+    expect(settled.data.payout.toNumber()).to.equal(multiPool.payout.toNumber() * wins);
+    expect(await connection.getBalance(player.publicKey)).to.equal(
+      playerBalanceBefore + settled.data.payout.toNumber()
+    );
+    expect(await connection.getBalance(multiGameVaultPDA)).to.equal(0);
+  });
+
+  it("should reject a multi-roll bet above MAX_ROLLS", async () => {
+    try {
+      await program.methods
+        .playMulti(
+          {
+            gameKind: { overUnder: {} },
+            targetNumber: 57,
+            rangeEnd: 0,
+            isUnder: true,
+            betAmount: new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+            gameSessionId: multi_game_session_id,
+            serverSeedHash: sha256(randomBytes(32)),
+            clientSeed: [...clientSeed],
+            slotHash: false,
+          },
+          11
+        )
+        .accounts({
+          owner: player.publicKey,
+          operator: operationAdmin.publicKey,
+        })
+        .signers([player, operationAdmin])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("InvalidRollCount");
+    }
+  });
+
+  it("should reject bets that exceed the vault exposure limit", async () => {
+    await program.methods
+      .setMaxLiabilityBps(new anchor.BN(1))
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    await applyConfig({ maxLiabilityBps: {} });
+
+    try {
+      await program.methods
+        .playGame({
+          gameKind: { overUnder: {} },
+          targetNumber: 57,
+          rangeEnd: 0,
+          isUnder: true,
+          betAmount: new anchor.BN(0.2 * LAMPORTS_PER_SOL),
+          gameSessionId: second_game_session_id,
+          serverSeedHash: sha256(secondServerSeed),
+          clientSeed: [...clientSeed],
+          slotHash: false,
+        })
+        .accounts({
+          owner: secondPlayer.publicKey,
+          operator: operationAdmin.publicKey,
+          playerPool: secondPlayerPoolPDA,
+          gameVault: secondGameVaultPDA,
+        })
+        .signers([secondPlayer, operationAdmin])
+        .rpc();
+
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("ExposureLimitExceeded");
+    } finally {
+      await program.methods
+        .setMaxLiabilityBps(new anchor.BN(5_000))
+        .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+        .signers([updateAdmin])
+        .rpc();
+      await applyConfig({ maxLiabilityBps: {} });
+    }
+  });
+
+  it("should cap the max win at a share of the casino vault", async () => {
+    const [dynamicPlayerPoolPDA] = PublicKey.findProgramAddressSync(
+      [secondPlayer.publicKey.toBuffer(), Buffer.from("player-pool"), dynamic_max_win_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const [dynamicGameVaultPDA] = PublicKey.findProgramAddressSync(
+      [secondPlayer.publicKey.toBuffer(), Buffer.from("vault-authority"), dynamic_max_win_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const dynamicServerSeed = randomBytes(32);
+
+    // Cap a single bet's net win just under 0.1 SOL of the current vault
+    const vaultBalance = await connection.getBalance(casinoVaultPDA);
+    const maxWinBps = Math.floor((0.1 * LAMPORTS_PER_SOL * 10_000) / vaultBalance);
+    await program.methods
+      .setDynamicMaxWinBps(new anchor.BN(maxWinBps))
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    await applyConfig({ dynamicMaxWinBps: {} });
+
+    const playDynamic = (betAmount: number) =>
+      program.methods
+        .playGame({
+          gameKind: { overUnder: {} },
+          targetNumber: 57,
+          rangeEnd: 0,
+          isUnder: true,
+          betAmount: new anchor.BN(betAmount),
+          gameSessionId: dynamic_max_win_game_session_id,
+          serverSeedHash: sha256(dynamicServerSeed),
+          clientSeed: [...clientSeed],
+          slotHash: false,
+        })
+        .accounts({
+          owner: secondPlayer.publicKey,
+          operator: operationAdmin.publicKey,
+          playerPool: dynamicPlayerPoolPDA,
+          gameVault: dynamicGameVaultPDA,
+        })
+        .signers([secondPlayer, operationAdmin])
+        .rpc();
+
+    try {
+      // Wins about 0.133 SOL net, over the cap
+      try {
+        await playDynamic(0.2 * LAMPORTS_PER_SOL);
+        expect.fail("Should have thrown error");
+      } catch (err) {
+        expect(err.toString()).to.contain("InvalidBetAmountMaxWinAmountViolation");
+      }
+
+      // Wins about 0.067 SOL net, under the cap
+      await playDynamic(0.1 * LAMPORTS_PER_SOL);
+      const dynamicPool = await program.account.playerPool.fetch(dynamicPlayerPoolPDA);
+      expect(dynamicPool.bet.toNumber()).to.equal(0.1 * LAMPORTS_PER_SOL);
+
+      await slotHashAfter(dynamicPool.betSlot);
+      await program.methods
+        .setResult({ gameSessionId: dynamic_max_win_game_session_id, serverSeed: [...dynamicServerSeed] })
+        .accounts({
+          owner: secondPlayer.publicKey,
+          operator: operationAdmin.publicKey,
+          gameVault: dynamicGameVaultPDA,
+          playerPool: dynamicPlayerPoolPDA,
+        })
+        .signers([operationAdmin])
+        .rpc();
+    } finally {
+      await program.methods
+        .setDynamicMaxWinBps(new anchor.BN(0))
+        .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+        .signers([updateAdmin])
+        .rpc();
+      await applyConfig({ dynamicMaxWinBps: {} });
+    }
+  });
+
+  it("should settle a bet on its locked payout and leave no lamports stranded", async () => {
+    const betAmount = playerPool.bet.toNumber();
+    expect(playerPool.rtp.toNumber()).to.equal(95);
+
+    // An RTP change after the bet is placed must not touch its payout
+    await program.methods
+      .setRtp(new anchor.BN(80))
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    await applyConfig({ rtp: {} });
+
+    const slotHash = await slotHashAfter(playerPool.betSlot);
+    const playerBalanceBefore = await connection.getBalance(player.publicKey);
+    const casinoBalanceBefore = await connection.getBalance(casinoVaultPDA);
 
     const tx = await program.methods
-      .setResult(true, game_session_id)
+      .setResult({
+        gameSessionId: game_session_id,
+        serverSeed: [...serverSeed],
+      })
       .accounts({
         owner: player.publicKey,
         operator: operationAdmin.publicKey,
@@ -268,24 +692,50 @@ describe("Dice Game", () => {
     tx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
     console.log(await connection.simulateTransaction(tx));
     const sig = await sendAndConfirmTransaction(connection, tx, [
-      player,
       operationAdmin,
     ]);
     console.log(`Win Sig => https://solscan.io/${sig}`);
 
-    // const playerPool = await program.account.playerPool.fetch(playerPoolPDA);
-    // console.log("PlayerPool after Win => ", playerPool);
-    // expect(playerPool.status).to.deep.equal({ win: {} });
+    const settled = (await getEvents(sig)).find((e) => e.name === "betSettled");
+    const expectedRoll = computeRoll(mixSlotHash(serverSeed, slotHash), clientSeed, game_session_id);
+    const payout = settled.data.payout.toNumber();
+    expect(settled.data.roll).to.equal(expectedRoll);
+    expect(settled.data.isWin).to.equal(expectedRoll < 57);
+    expect(settled.data.multiplier.eq(playerPool.multiplier)).to.be.true;
+    expect(payout).to.equal(settled.data.isWin ? playerPool.payout.toNumber() : 0);
+
+    expect(await connection.getAccountInfo(playerPoolPDA)).to.be.null;
+    expect(await connection.getBalance(gameVaultPDA)).to.equal(0);
+    expect(await connection.getBalance(player.publicKey)).to.equal(
+      playerBalanceBefore + payout
+    );
+    expect(await connection.getBalance(casinoVaultPDA)).to.equal(
+      casinoBalanceBefore - (payout - betAmount)
+    );
 
-    const finalBalance = await connection.getBalance(gameVaultPDA);
-    console.log("Final Game Balance after win is {}", finalBalance);
+    await program.methods
+      .setRtp(new anchor.BN(95))
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    await applyConfig({ rtp: {} });
   });
 
-  it("should process a loss correctly", async () => {
+  it("should settle a second player's bet and leave no lamports stranded", async () => {
     const betAmount = new anchor.BN(0.2 * LAMPORTS_PER_SOL); // 0.2 SOL
 
     const tx = await program.methods
-        .playGame(57, true, betAmount, second_game_session_id)
+        .playGame({
+          gameKind: { overUnder: {} },
+          targetNumber: 57,
+          rangeEnd: 0,
+          isUnder: true,
+          betAmount,
+          gameSessionId: second_game_session_id,
+          serverSeedHash: sha256(secondServerSeed),
+          clientSeed: [...clientSeed],
+          slotHash: false,
+        })
         .accounts({
           owner: secondPlayer.publicKey,
           operator: operationAdmin.publicKey,
@@ -304,9 +754,16 @@ describe("Dice Game", () => {
       ]);
       console.log(`Second PlaceBet Sig => https://solscan.io/${sig}`);
 
-    // Then set as loss
-    const lossTx = await program.methods
-      .setResult(false, second_game_session_id)
+    const secondPool = await program.account.playerPool.fetch(secondPlayerPoolPDA);
+    const slotHash = await slotHashAfter(secondPool.betSlot);
+    const playerBalanceBefore = await connection.getBalance(secondPlayer.publicKey);
+    const casinoBalanceBefore = await connection.getBalance(casinoVaultPDA);
+
+    const secondTx = await program.methods
+      .setResult({
+        gameSessionId: second_game_session_id,
+        serverSeed: [...secondServerSeed],
+      })
       .accounts({
         owner: secondPlayer.publicKey,
         operator: operationAdmin.publicKey,
@@ -315,110 +772,1683 @@ describe("Dice Game", () => {
       })
       .transaction();
 
-    lossTx.feePayer = operationAdmin.publicKey;
-    lossTx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
-    console.log(await connection.simulateTransaction(lossTx));
-    const lossSig = await sendAndConfirmTransaction(connection, lossTx, [
-      secondPlayer,
+    secondTx.feePayer = operationAdmin.publicKey;
+    secondTx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
+    console.log(await connection.simulateTransaction(secondTx));
+    const secondSig = await sendAndConfirmTransaction(connection, secondTx, [
       operationAdmin,
     ]);
-    console.log(`Set Loss Sig => https://solscan.io/${lossSig}`);
+    console.log(`Second SetResult Sig => https://solscan.io/${secondSig}`);
 
-    // playerPool = await program.account.playerPool.fetch(playerPoolPDA);
-    // console.log("PlayerPool after lose of 2nd round: ", playerPool);
+    const settled = (await getEvents(secondSig)).find((e) => e.name === "betSettled");
+    const roll = computeRoll(mixSlotHash(secondServerSeed, slotHash), clientSeed, second_game_session_id);
+    const payout = settled.data.payout.toNumber();
+    expect(settled.data.roll).to.equal(roll);
+    expect(settled.data.isWin).to.equal(roll < 57);
 
-    // expect(playerPool.status).to.deep.equal({ lose: {} });
+    expect(await connection.getAccountInfo(secondPlayerPoolPDA)).to.be.null;
+    expect(await connection.getBalance(secondGameVaultPDA)).to.equal(0);
+    expect(await connection.getBalance(secondPlayer.publicKey)).to.equal(
+      playerBalanceBefore + payout
+    );
+    expect(await connection.getBalance(casinoVaultPDA)).to.equal(
+      casinoBalanceBefore + betAmount.toNumber() - payout
+    );
   });
 
-  it("should allow admin to withdraw funds", async () => {
-    const recipient = Keypair.generate();
-    const amount = new anchor.BN(0.5 * LAMPORTS_PER_SOL);
+  it("should play and settle a token bet", async () => {
+    const mint = await createMint(
+      connection,
+      superAdmin,
+      superAdmin.publicKey,
+      null,
+      6
+    );
+    const playerAta = await getOrCreateAssociatedTokenAccount(
+      connection,
+      superAdmin,
+      mint,
+      player.publicKey
+    );
+    await mintTo(connection, superAdmin, mint, playerAta.address, superAdmin, 1_000_000_000);
 
-    const tx = await program.methods
-      .withdraw(amount)
+    const [casinoTokenVaultPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault-authority"), mint.toBuffer()],
+      program.programId
+    );
+    const [tokenPlayerPoolPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("player-pool"), token_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .addToken(new anchor.BN(1_000_000), new anchor.BN(100_000_000))
       .accounts({
-        financialAdmin: financialAdmin.publicKey,
-        recipient: recipient.publicKey,
+        admin: updateAdmin.publicKey,
+        tokenMint: mint,
       })
-      .transaction();
-    tx.feePayer = financialAdmin.publicKey;
-    tx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
-    console.log(await connection.simulateTransaction(tx));
-    const sig = await sendAndConfirmTransaction(connection, tx, [
-      financialAdmin,
-    ]);
-    console.log(`RTP update Sig => https://solscan.io/${sig}`);
+      .signers([updateAdmin])
+      .rpc();
 
-    const recipientBalance = await connection.getBalance(recipient.publicKey);
-    expect(recipientBalance).to.equal(amount.toNumber());
-  });
+    // Fund the casino token vault so it can cover wins
+    const adminAta = await getOrCreateAssociatedTokenAccount(
+      connection,
+      superAdmin,
+      mint,
+      superAdmin.publicKey
+    );
+    await mintTo(connection, superAdmin, mint, adminAta.address, superAdmin, 500_000_000);
+    await transfer(connection, superAdmin, adminAta.address, casinoTokenVaultPDA, superAdmin, 500_000_000);
 
-  it("should allow updating RTP", async () => {
-    const newRtp = new anchor.BN(90);
-    const tx = await program.methods
-      .setRtp(newRtp)
+    const tokenServerSeed = randomBytes(32);
+    const betAmount = new anchor.BN(10_000_000);
+    await program.methods
+      .playGameToken({
+        gameKind: { overUnder: {} },
+        targetNumber: 57,
+        rangeEnd: 0,
+        isUnder: true,
+        betAmount,
+        gameSessionId: token_game_session_id,
+        serverSeedHash: sha256(tokenServerSeed),
+        clientSeed: [...clientSeed],
+        slotHash: false,
+      })
       .accounts({
-        admin: updateAdmin.publicKey,
-        globalPool: globalAuthorityPDA,
+        owner: player.publicKey,
+        operator: operationAdmin.publicKey,
+        playerPool: tokenPlayerPoolPDA,
+        tokenMint: mint,
+        playerTokenAccount: playerAta.address,
       })
-      .transaction();
+      .signers([player, operationAdmin])
+      .rpc();
 
-    tx.feePayer = updateAdmin.publicKey;
-    tx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
-    console.log(await connection.simulateTransaction(tx));
-    const sig = await sendAndConfirmTransaction(connection, tx, [updateAdmin]);
-    console.log(`RTP update Sig => https://solscan.io/${sig}`);
+    const tokenPool = await program.account.playerPool.fetch(tokenPlayerPoolPDA);
+    const tokenSlotHash = await slotHashAfter(tokenPool.betSlot);
+    const sig = await program.methods
+      .setResultToken({
+        gameSessionId: token_game_session_id,
+        serverSeed: [...tokenServerSeed],
+      })
+      .accounts({
+        operator: operationAdmin.publicKey,
+        owner: player.publicKey,
+        playerPool: tokenPlayerPoolPDA,
+        tokenMint: mint,
+        playerTokenAccount: playerAta.address,
+      })
+      .signers([operationAdmin])
+      .rpc({ commitment: "confirmed" });
 
-    const globalAuthority = await program.account.globalPool.fetch(
-      globalAuthorityPDA
+    const settled = (await getEvents(sig)).find((e) => e.name === "betSettled");
+    const roll = computeRoll(mixSlotHash(tokenServerSeed, tokenSlotHash), clientSeed, token_game_session_id);
+    const playerBalance = (await getAccount(connection, playerAta.address)).amount;
+    const expected = BigInt(1_000_000_000) - BigInt(betAmount.toString()) + BigInt(settled.data.payout.toString());
+
+    expect(settled.data.roll).to.equal(roll);
+    expect(settled.data.mint.toString()).to.equal(mint.toString());
+    expect(playerBalance.toString()).to.equal(expected.toString());
+    expect(await connection.getAccountInfo(tokenPlayerPoolPDA)).to.be.null;
+
+    // New token limits wait out the config timelock
+    const [tokenConfigPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("token-config"), mint.toBuffer()],
+      program.programId
     );
-    expect(newRtp.eq(globalAuthority.rtp)).to.be.true;
-  });
-  
-  it("should allow updating MinNum", async () => {
-    const newMinNum = 20;
-    const tx = await program.methods
-      .setMinNum(newMinNum)
+    await program.methods
+      .setTokenConfig(new anchor.BN(2_000_000), new anchor.BN(50_000_000))
       .accounts({
         admin: updateAdmin.publicKey,
         globalPool: globalAuthorityPDA,
+        tokenConfig: tokenConfigPDA,
       })
-      .transaction();
+      .signers([updateAdmin])
+      .rpc();
 
-    tx.feePayer = updateAdmin.publicKey;
-    tx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
-    console.log(await connection.simulateTransaction(tx));
-    const sig = await sendAndConfirmTransaction(connection, tx, [updateAdmin]);
-    console.log(`MinTargetNumber update Sig => https://solscan.io/${sig}`);
+    try {
+      await program.methods
+        .applyTokenConfig()
+        .accounts({ tokenConfig: tokenConfigPDA })
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("ConfigTimelocked");
+    }
+    let tokenConfig = await program.account.tokenConfig.fetch(tokenConfigPDA);
+    expect(tokenConfig.minBetAmount.toNumber()).to.equal(1_000_000);
 
-    const globalAuthority = await program.account.globalPool.fetch(
-      globalAuthorityPDA
-    );
-    expect(newMinNum === globalAuthority.minNum).to.be.true;
-  });
+    // and can be called off before they apply
+    await program.methods
+      .cancelTokenConfig()
+      .accounts({
+        admin: updateAdmin.publicKey,
+        globalPool: globalAuthorityPDA,
+        tokenConfig: tokenConfigPDA,
+      })
+      .signers([updateAdmin])
+      .rpc();
+    tokenConfig = await program.account.tokenConfig.fetch(tokenConfigPDA);
+    expect(tokenConfig.pending.effectiveAt.toNumber()).to.equal(0);
+    try {
+      await program.methods
+        .applyTokenConfig()
+        .accounts({ tokenConfig: tokenConfigPDA })
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("NoPendingConfig");
+    }
 
-  it("should allow updating authorities", async () => {
-    const tx = await program.methods
-      .setOperationAuthority(newAdmin.publicKey)
+    await program.methods
+      .setTokenConfig(new anchor.BN(2_000_000), new anchor.BN(50_000_000))
       .accounts({
-        admin: superAdmin.publicKey,
+        admin: updateAdmin.publicKey,
         globalPool: globalAuthorityPDA,
+        tokenConfig: tokenConfigPDA,
       })
-      .transaction();
-    tx.feePayer = superAdmin.publicKey;
-    tx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
-    console.log(await connection.simulateTransaction(tx));
-    const sig = await sendAndConfirmTransaction(connection, tx, [superAdmin]);
-    console.log(`RTP update Sig => https://solscan.io/${sig}`);
+      .signers([updateAdmin])
+      .rpc();
 
-    const globalAuthority = await program.account.globalPool.fetch(
-      globalAuthorityPDA
-    );
-    expect(globalAuthority.operationAuthority.toString()).to.equal(
-      newAdmin.publicKey.toString()
+    await afterTimelock(() =>
+      program.methods
+        .applyTokenConfig()
+        .accounts({ tokenConfig: tokenConfigPDA })
+        .rpc()
     );
+    tokenConfig = await program.account.tokenConfig.fetch(tokenConfigPDA);
+    expect(tokenConfig.minBetAmount.toNumber()).to.equal(2_000_000);
+    expect(tokenConfig.maxWinAmount.toNumber()).to.equal(50_000_000);
   });
-});
+
+  it("should pay a lapsed commit-reveal bet its locked payout", async () => {
+    const [refundPlayerPoolPDA] = PublicKey.findProgramAddressSync(
+      [secondPlayer.publicKey.toBuffer(), Buffer.from("player-pool"), refund_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const [refundGameVaultPDA] = PublicKey.findProgramAddressSync(
+      [secondPlayer.publicKey.toBuffer(), Buffer.from("vault-authority"), refund_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .setBetTimeout(new anchor.BN(1))
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    await applyConfig({ betTimeout: {} });
+
+    const betAmount = new anchor.BN(0.2 * LAMPORTS_PER_SOL);
+    await program.methods
+      .playGame({
+        gameKind: { overUnder: {} },
+        targetNumber: 57,
+        rangeEnd: 0,
+        isUnder: true,
+        betAmount,
+        gameSessionId: refund_game_session_id,
+        serverSeedHash: sha256(randomBytes(32)),
+        clientSeed: [...clientSeed],
+        slotHash: false,
+      })
+      .accounts({
+        owner: secondPlayer.publicKey,
+        operator: operationAdmin.publicKey,
+        playerPool: refundPlayerPoolPDA,
+        gameVault: refundGameVaultPDA,
+      })
+      .signers([secondPlayer, operationAdmin])
+      .rpc();
+
+    await sleep(3000);
+    const { payout } = await program.account.playerPool.fetch(refundPlayerPoolPDA);
+    const balanceBefore = await connection.getBalance(secondPlayer.publicKey);
+
+    const sig = await program.methods
+      .refundExpiredBet(refund_game_session_id)
+      .accounts({
+        owner: secondPlayer.publicKey,
+        operator: operationAdmin.publicKey,
+      })
+      .signers([secondPlayer])
+      .rpc({ commitment: "confirmed" });
+
+    const balanceAfter = await connection.getBalance(secondPlayer.publicKey);
+    // Only the operator could have revealed the bet, so letting it lapse pays it as a win.
+    // The player pays the transaction fee, everything else is the payout.
+    expect(payout.toNumber()).to.be.greaterThan(betAmount.toNumber());
+    expect(balanceAfter - balanceBefore).to.be.greaterThan(payout.toNumber() - 10_000);
+    const paid = (await getEvents(sig)).find((e) => e.name === "expiredBetPaid");
+    expect(paid.data.amount.toString()).to.equal(payout.toString());
+    expect(await connection.getAccountInfo(refundPlayerPoolPDA)).to.be.null;
+
+    await program.methods
+      .setBetTimeout(new anchor.BN(60 * 60))
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    await applyConfig({ betTimeout: {} });
+  });
+
+  it("should block betting while paused", async () => {
+    const PAUSE_BETTING = 1;
+    await program.methods
+      .setPaused(PAUSE_BETTING)
+      .accounts({ admin: superAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([superAdmin])
+      .rpc();
+
+    try {
+      await program.methods
+        .playGame({
+          gameKind: { overUnder: {} },
+          targetNumber: 57,
+          rangeEnd: 0,
+          isUnder: true,
+          betAmount: new anchor.BN(0.2 * LAMPORTS_PER_SOL),
+          gameSessionId: second_game_session_id,
+          serverSeedHash: sha256(randomBytes(32)),
+          clientSeed: [...clientSeed],
+          slotHash: false,
+        })
+        .accounts({
+          owner: secondPlayer.publicKey,
+          operator: operationAdmin.publicKey,
+          playerPool: secondPlayerPoolPDA,
+          gameVault: secondGameVaultPDA,
+        })
+        .signers([secondPlayer, operationAdmin])
+        .rpc();
+
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("Paused");
+    } finally {
+      await program.methods
+        .setPaused(0)
+        .accounts({ admin: superAdmin.publicKey, globalPool: globalAuthorityPDA })
+        .signers([superAdmin])
+        .rpc();
+    }
+  });
+
+  it("should let the guardian add pause flags but not lift them", async () => {
+    const PAUSE_BETTING = 1;
+    const PAUSE_SETTLEMENT = 2;
+    const guardian = Keypair.generate();
+    await program.methods
+      .setGuardian(guardian.publicKey)
+      .accounts({ admin: superAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([superAdmin])
+      .rpc();
+
+    const setPaused = (admin: Keypair, flags: number) =>
+      program.methods
+        .setPaused(flags)
+        .accounts({ admin: admin.publicKey, globalPool: globalAuthorityPDA })
+        .signers([admin])
+        .rpc();
+    const pauseFlags = async () => (await program.account.globalPool.fetch(globalAuthorityPDA)).pauseFlags;
+
+    await setPaused(superAdmin, PAUSE_BETTING);
+    await setPaused(guardian, PAUSE_SETTLEMENT);
+    expect(await pauseFlags()).to.equal(PAUSE_BETTING | PAUSE_SETTLEMENT);
+
+    // Resuming is the super admin's call
+    await setPaused(guardian, 0);
+    expect(await pauseFlags()).to.equal(PAUSE_BETTING | PAUSE_SETTLEMENT);
+
+    await setPaused(superAdmin, 0);
+    expect(await pauseFlags()).to.equal(0);
+  });
+
+  it("should settle a VRF bet from its randomness account", async () => {
+    const [vrfPlayerPoolPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("player-pool"), vrf_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const [vrfGameVaultPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("vault-authority"), vrf_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const [vrfRandomnessPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("orao-vrf-randomness-request"), vrfPlayerPoolPDA.toBuffer()],
+      mockVrf.programId
+    );
+
+    await program.methods
+      .setRandomnessMode({ vrf: {} }, mockVrf.programId)
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+
+    // The mode switch is held behind the config timelock
+    try {
+      await program.methods
+        .applyRandomnessMode()
+        .accounts({ globalPool: globalAuthorityPDA })
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("ConfigTimelocked");
+    }
+    let globalAuthority = await program.account.globalPool.fetch(globalAuthorityPDA);
+    expect(globalAuthority.randomnessMode).to.deep.equal({ commitReveal: {} });
+
+    // A queued switch can be called off before it applies
+    await program.methods
+      .cancelRandomnessMode()
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    globalAuthority = await program.account.globalPool.fetch(globalAuthorityPDA);
+    expect(globalAuthority.pendingRandomnessMode.effectiveAt.toNumber()).to.equal(0);
+    try {
+      await program.methods
+        .applyRandomnessMode()
+        .accounts({ globalPool: globalAuthorityPDA })
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("NoPendingConfig");
+    }
+
+    await program.methods
+      .setRandomnessMode({ vrf: {} }, mockVrf.programId)
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    await applyRandomnessMode();
+    globalAuthority = await program.account.globalPool.fetch(globalAuthorityPDA);
+    expect(globalAuthority.randomnessMode).to.deep.equal({ vrf: {} });
+
+    await program.methods
+      .playGame({
+        gameKind: { overUnder: {} },
+        targetNumber: 57,
+        rangeEnd: 0,
+        isUnder: true,
+        betAmount: new anchor.BN(0.2 * LAMPORTS_PER_SOL),
+        gameSessionId: vrf_game_session_id,
+        serverSeedHash: new Array(32).fill(0),
+        clientSeed: [...clientSeed],
+        slotHash: false,
+      })
+      .accounts({
+        owner: player.publicKey,
+        operator: operationAdmin.publicKey,
+        playerPool: vrfPlayerPoolPDA,
+        gameVault: vrfGameVaultPDA,
+        vrfRandomness: vrfRandomnessPDA,
+      })
+      .signers([player, operationAdmin])
+      .rpc();
+
+    const randomness = randomBytes(64);
+    await mockVrf.methods
+      .request([...vrfPlayerPoolPDA.toBuffer()])
+      .accounts({ payer: operationAdmin.publicKey })
+      .signers([operationAdmin])
+      .rpc();
+    await mockVrf.methods
+      .fulfill([...randomness])
+      .accounts({ fulfiller: operationAdmin.publicKey, randomness: vrfRandomnessPDA })
+      .signers([operationAdmin])
+      .rpc();
+
+    // Seed reveal is refused for a VRF bet
+    try {
+      await program.methods
+        .setResult({ gameSessionId: vrf_game_session_id, serverSeed: new Array(32).fill(0) })
+        .accounts({
+          owner: player.publicKey,
+          operator: operationAdmin.publicKey,
+          gameVault: vrfGameVaultPDA,
+          playerPool: vrfPlayerPoolPDA,
+        })
+        .signers([operationAdmin])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("WrongRandomnessMode");
+    }
+
+    // Once the roll is fulfilled the bet can only settle, waiting out the timeout gets no refund
+    const setBetTimeout = async (timeout: number) => {
+      await program.methods
+        .setBetTimeout(new anchor.BN(timeout))
+        .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+        .signers([updateAdmin])
+        .rpc();
+      await applyConfig({ betTimeout: {} });
+    };
+    await setBetTimeout(1);
+    try {
+      await program.methods
+        .refundExpiredBet(vrf_game_session_id)
+        .accounts({
+          owner: player.publicKey,
+          operator: operationAdmin.publicKey,
+          vrfRandomness: vrfRandomnessPDA,
+        })
+        .signers([player])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("VrfBetFulfilled");
+    }
+    await setBetTimeout(60 * 60);
+
+    // The bet keeps the VRF program it was placed under after the casino leaves Vrf mode
+    const vrfPool = await program.account.playerPool.fetch(vrfPlayerPoolPDA);
+    expect(vrfPool.vrfProgram.toString()).to.equal(mockVrf.programId.toString());
+    await program.methods
+      .setRandomnessMode({ commitReveal: {} }, PublicKey.default)
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    await applyRandomnessMode();
+    globalAuthority = await program.account.globalPool.fetch(globalAuthorityPDA);
+    expect(globalAuthority.randomnessMode).to.deep.equal({ commitReveal: {} });
+    expect(globalAuthority.vrfProgram.toString()).to.equal(mockVrf.programId.toString());
+
+    // Anyone can crank the settlement, the provider wallet only pays the fee
+    const sig = await program.methods
+      .setResultVrf(vrf_game_session_id)
+      .accounts({
+        owner: player.publicKey,
+        operator: operationAdmin.publicKey,
+        vrfRandomness: vrfRandomnessPDA,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const settled = (await getEvents(sig)).find((e) => e.name === "betSettled");
+    expect(settled.data.roll).to.equal(
+      computeRoll(randomness.subarray(0, 32), clientSeed, vrf_game_session_id)
+    );
+    expect(await connection.getAccountInfo(vrfPlayerPoolPDA)).to.be.null;
+    expect(await connection.getBalance(vrfGameVaultPDA)).to.equal(0);
+  });
+
+  it("should let anyone settle a small bet from the next slot hash", async () => {
+    const [slotPlayerPoolPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("player-pool"), slot_hash_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const [slotGameVaultPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("vault-authority"), slot_hash_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const slotHashBet = {
+      gameKind: { overUnder: {} },
+      targetNumber: 57,
+      rangeEnd: 0,
+      isUnder: true,
+      betAmount: new anchor.BN(0.2 * LAMPORTS_PER_SOL),
+      gameSessionId: slot_hash_game_session_id,
+      serverSeedHash: new Array(32).fill(0),
+      clientSeed: [...clientSeed],
+      slotHash: true,
+    };
+    const slotHashAccounts = {
+      owner: player.publicKey,
+      operator: operationAdmin.publicKey,
+      playerPool: slotPlayerPoolPDA,
+      gameVault: slotGameVaultPDA,
+    };
+
+    // Slot hashes are a per-bet opt-in, never the casino-wide mode
+    try {
+      await program.methods
+        .setRandomnessMode({ slotHash: {} }, PublicKey.default)
+        .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+        .signers([updateAdmin])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("SlotHashModeNotGlobal");
+    }
+
+    // Disabled until slot_hash_max_bet is raised
+    try {
+      await program.methods
+        .playGame(slotHashBet)
+        .accounts(slotHashAccounts)
+        .signers([player, operationAdmin])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("SlotHashBetTooLarge");
+    }
+
+    await program.methods
+      .setSlotHashMaxBet(new anchor.BN(0.5 * LAMPORTS_PER_SOL))
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    await applyConfig({ slotHashMaxBet: {} });
+
+    await program.methods
+      .playGame(slotHashBet)
+      .accounts(slotHashAccounts)
+      .signers([player, operationAdmin])
+      .rpc();
+
+    await sleep(2000);
+
+    // No casino key signs, the provider wallet only pays the fee
+    const sig = await program.methods
+      .setResultSlotHash(slot_hash_game_session_id)
+      .accounts({ owner: player.publicKey, operator: operationAdmin.publicKey })
+      .rpc({ commitment: "confirmed" });
+
+    const settled = (await getEvents(sig)).find((e) => e.name === "betSettled");
+    expect(settled.data.roll).to.be.within(0, 99);
+    expect(await connection.getAccountInfo(slotPlayerPoolPDA)).to.be.null;
+    expect(await connection.getBalance(slotGameVaultPDA)).to.equal(0);
+  });
+
+  it("should forfeit an unsettled slot hash bet instead of refunding it", async () => {
+    const [forfeitPlayerPoolPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("player-pool"), forfeit_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const [forfeitGameVaultPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("vault-authority"), forfeit_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+
+    await program.methods
+      .setBetTimeout(new anchor.BN(1))
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    await applyConfig({ betTimeout: {} });
+
+    const betAmount = new anchor.BN(0.2 * LAMPORTS_PER_SOL);
+    await program.methods
+      .playGame({
+        gameKind: { overUnder: {} },
+        targetNumber: 57,
+        rangeEnd: 0,
+        isUnder: true,
+        betAmount,
+        gameSessionId: forfeit_game_session_id,
+        serverSeedHash: new Array(32).fill(0),
+        clientSeed: [...clientSeed],
+        slotHash: true,
+      })
+      .accounts({
+        owner: player.publicKey,
+        operator: operationAdmin.publicKey,
+        playerPool: forfeitPlayerPoolPDA,
+        gameVault: forfeitGameVaultPDA,
+      })
+      .signers([player, operationAdmin])
+      .rpc();
+
+    // The player saw a losing roll and never settled it
+    await sleep(3000);
+
+    try {
+      await program.methods
+        .refundExpiredBet(forfeit_game_session_id)
+        .accounts({ owner: player.publicKey, operator: operationAdmin.publicKey })
+        .signers([player])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("SlotHashBetNotRefundable");
+    }
+
+    const casinoBalanceBefore = await connection.getBalance(casinoVaultPDA);
+    await program.methods
+      .forfeitExpiredBet(forfeit_game_session_id)
+      .accounts({ owner: player.publicKey, operator: operationAdmin.publicKey })
+      .rpc();
+
+    expect(await connection.getBalance(casinoVaultPDA)).to.equal(
+      casinoBalanceBefore + betAmount.toNumber()
+    );
+    expect(await connection.getAccountInfo(forfeitPlayerPoolPDA)).to.be.null;
+    expect(await connection.getBalance(forfeitGameVaultPDA)).to.equal(0);
+
+    await program.methods
+      .setBetTimeout(new anchor.BN(60 * 60))
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    await applyConfig({ betTimeout: {} });
+  });
+
+  it("should roll an auto-bet session within the player's limits", async () => {
+    const [autoBetPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("auto-bet"), player.publicKey.toBuffer()],
+      program.programId
+    );
+
+    // Operator hash chain, revealed from the head: sha256(seeds[i]) is the commitment for seeds[i]
+    const seeds = [randomBytes(32)];
+    for (let i = 0; i < 2; i++) {
+      seeds.unshift(Buffer.from(sha256(seeds[0])));
+    }
+
+    const deposit = new anchor.BN(0.5 * LAMPORTS_PER_SOL);
+    await program.methods
+      .startAutoBet({
+        deposit,
+        baseBet: new anchor.BN(0.1 * LAMPORTS_PER_SOL),
+        maxBet: new anchor.BN(0.4 * LAMPORTS_PER_SOL),
+        onWinBps: new anchor.BN(0),
+        onLossBps: new anchor.BN(20_000),
+        stopLoss: new anchor.BN(0.5 * LAMPORTS_PER_SOL),
+        stopProfit: new anchor.BN(0.3 * LAMPORTS_PER_SOL),
+        gameKind: { overUnder: {} },
+        targetNumber: 57,
+        rangeEnd: 0,
+        isUnder: true,
+        serverSeedHash: sha256(seeds[0]),
+        clientSeed: [...clientSeed],
+      })
+      .accounts({ player: player.publicKey, operator: operationAdmin.publicKey })
+      .signers([player, operationAdmin])
+      .rpc();
+
+    // Only the head of the chain is accepted
+    try {
+      await program.methods
+        .autoBetRoll([...seeds[1]])
+        .accounts({ operator: operationAdmin.publicKey, autoBetSession: autoBetPDA })
+        .signers([operationAdmin])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("InvalidServerSeed");
+    }
+
+    let balance = deposit.toNumber();
+    for (let nonce = 0; nonce < seeds.length; nonce++) {
+      const { lastSlot } = await program.account.autoBetSession.fetch(autoBetPDA);
+      const slotHash = await slotHashAfter(lastSlot);
+      const sig = await program.methods
+        .autoBetRoll([...seeds[nonce]])
+        .accounts({ operator: operationAdmin.publicKey, autoBetSession: autoBetPDA })
+        .signers([operationAdmin])
+        .rpc({ commitment: "confirmed" });
+
+      const rolled = (await getEvents(sig)).find((e) => e.name === "autoBetRolled");
+      // The seed committed at the start is mixed with a slot hash from after the previous roll
+      expect(Buffer.from(rolled.data.slotHash).equals(slotHash)).to.be.true;
+      const roll = computeRoll(mixSlotHash(seeds[nonce], slotHash), clientSeed, new anchor.BN(nonce));
+      expect(rolled.data.roll).to.equal(roll);
+      expect(rolled.data.isWin).to.equal(roll < 57);
+
+      balance += rolled.data.payout.toNumber() - rolled.data.betAmount.toNumber();
+      expect(rolled.data.balance.toNumber()).to.equal(balance);
+      if (!rolled.data.active) break;
+    }
+
+    const playerBalanceBefore = await connection.getBalance(player.publicKey);
+    const sessionLamports = await connection.getBalance(autoBetPDA);
+    await program.methods
+      .stopAutoBet()
+      .accounts({ player: player.publicKey })
+      .signers([player])
+      .rpc();
+
+    expect(await connection.getAccountInfo(autoBetPDA)).to.be.null;
+    // Balance and rent come back, less the transaction fee
+    expect(await connection.getBalance(player.publicKey)).to.be.greaterThan(
+      playerBalanceBefore + sessionLamports - 10_000
+    );
+  });
+
+  it("should bet from a player balance under the operator's allowance", async () => {
+    const [playerBalancePDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("player-balance"), player.publicKey.toBuffer()],
+      program.programId
+    );
+    const [balancePlayerPoolPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("player-pool"), balance_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const [balanceGameVaultPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("vault-authority"), balance_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const betAmount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const balanceServerSeed = randomBytes(32);
+    const betParams = {
+      gameKind: { overUnder: {} },
+      targetNumber: 50,
+      rangeEnd: 0,
+      isUnder: true,
+      betAmount,
+      gameSessionId: balance_game_session_id,
+      serverSeedHash: sha256(balanceServerSeed),
+      clientSeed: [...clientSeed],
+      slotHash: false,
+    };
+
+    await program.methods
+      .depositBalance(new anchor.BN(LAMPORTS_PER_SOL))
+      .accounts({ player: player.publicKey })
+      .signers([player])
+      .rpc();
+
+    // No allowance yet, so the operator can't bet alone
+    try {
+      await program.methods
+        .playGameBalance(betParams)
+        .accounts({ owner: player.publicKey, operator: operationAdmin.publicKey })
+        .signers([operationAdmin])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("InsufficientAllowance");
+    }
+
+    const expiresAt = Math.floor(Date.now() / 1000) + 3600;
+    await program.methods
+      .setBalanceAllowance(betAmount, new anchor.BN(expiresAt))
+      .accounts({ player: player.publicKey })
+      .signers([player])
+      .rpc();
+
+    // The stake leaves the balance for the casino vault and is owed back on a win, so the
+    // exposure limit counts it on top of the 0.09 SOL net win
+    const setLiabilityCap = async (liability: number) => {
+      const vaultBalance = await connection.getBalance(casinoVaultPDA);
+      await program.methods
+        .setMaxLiabilityBps(new anchor.BN(Math.ceil((liability * 10_000) / vaultBalance)))
+        .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+        .signers([updateAdmin])
+        .rpc();
+      await applyConfig({ maxLiabilityBps: {} });
+    };
+    const { outstandingLiability } = await program.account.globalPool.fetch(globalAuthorityPDA);
+    const liabilityBefore = outstandingLiability.toNumber();
+
+    await setLiabilityCap(liabilityBefore + 0.14 * LAMPORTS_PER_SOL);
+    try {
+      await program.methods
+        .playGameBalance(betParams)
+        .accounts({ owner: player.publicKey, operator: operationAdmin.publicKey })
+        .signers([operationAdmin])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("ExposureLimitExceeded");
+    }
+    await setLiabilityCap(liabilityBefore + 0.19 * LAMPORTS_PER_SOL);
+
+    const casinoBalanceBefore = await connection.getBalance(casinoVaultPDA);
+    await program.methods
+      .playGameBalance(betParams)
+      .accounts({ owner: player.publicKey, operator: operationAdmin.publicKey })
+      .signers([operationAdmin])
+      .rpc();
+
+    let balance = await program.account.playerBalance.fetch(playerBalancePDA);
+    expect(balance.balance.toNumber()).to.equal(0.9 * LAMPORTS_PER_SOL);
+    expect(balance.allowance.toNumber()).to.equal(0);
+    const globalAuthority = await program.account.globalPool.fetch(globalAuthorityPDA);
+    expect(globalAuthority.outstandingLiability.toNumber()).to.equal(
+      liabilityBefore + 0.19 * LAMPORTS_PER_SOL
+    );
+
+    await program.methods
+      .setMaxLiabilityBps(new anchor.BN(5_000))
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    await applyConfig({ maxLiabilityBps: {} });
+    expect(await connection.getBalance(casinoVaultPDA)).to.equal(
+      casinoBalanceBefore + betAmount.toNumber()
+    );
+
+    const balancePool = await program.account.playerPool.fetch(balancePlayerPoolPDA);
+    await slotHashAfter(balancePool.betSlot);
+    const sig = await program.methods
+      .setResult({ gameSessionId: balance_game_session_id, serverSeed: [...balanceServerSeed] })
+      .accounts({
+        owner: player.publicKey,
+        operator: operationAdmin.publicKey,
+        gameVault: balanceGameVaultPDA,
+        playerPool: balancePlayerPoolPDA,
+        playerBalance: playerBalancePDA,
+      })
+      .signers([operationAdmin])
+      .rpc({ commitment: "confirmed" });
+
+    // The whole payout lands in the balance, the wallet is untouched
+    const settled = (await getEvents(sig)).find((e) => e.name === "betSettled");
+    const payout = settled.data.payout.toNumber();
+    balance = await program.account.playerBalance.fetch(playerBalancePDA);
+    expect(balance.balance.toNumber()).to.equal(0.9 * LAMPORTS_PER_SOL + payout);
+
+    const walletBefore = await connection.getBalance(player.publicKey);
+    await program.methods
+      .withdrawBalance(balance.balance)
+      .accounts({ player: player.publicKey })
+      .signers([player])
+      .rpc();
+
+    balance = await program.account.playerBalance.fetch(playerBalancePDA);
+    expect(balance.balance.toNumber()).to.equal(0);
+    expect(await connection.getBalance(player.publicKey)).to.be.greaterThan(
+      walletBefore + 0.9 * LAMPORTS_PER_SOL + payout - 10_000
+    );
+  });
+
+  it("should let a session key bet within its caps", async () => {
+    const sessionKey = Keypair.generate();
+    const [sessionTokenPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("session-token"), player.publicKey.toBuffer(), sessionKey.publicKey.toBuffer()],
+      program.programId
+    );
+    const [playerBalancePDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("player-balance"), player.publicKey.toBuffer()],
+      program.programId
+    );
+    const sessionPlayerPoolPDA = (id: anchor.BN) =>
+      PublicKey.findProgramAddressSync(
+        [player.publicKey.toBuffer(), Buffer.from("player-pool"), id.toArrayLike(Buffer, "be", 8)],
+        program.programId
+      )[0];
+    const [sessionGameVaultPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("vault-authority"), session_key_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const betAmount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const sessionServerSeed = randomBytes(32);
+    const betParams = (id: anchor.BN) => ({
+      gameKind: { overUnder: {} },
+      targetNumber: 50,
+      rangeEnd: 0,
+      isUnder: true,
+      betAmount,
+      gameSessionId: id,
+      serverSeedHash: sha256(sessionServerSeed),
+      clientSeed: [...clientSeed],
+      slotHash: false,
+    });
+
+    await program.methods
+      .depositBalance(new anchor.BN(0.5 * LAMPORTS_PER_SOL))
+      .accounts({ player: player.publicKey })
+      .signers([player])
+      .rpc();
+
+    await program.methods
+      .createSessionToken({
+        sessionKey: sessionKey.publicKey,
+        expiresAt: new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+        maxSpend: betAmount,
+        maxBet: betAmount,
+      })
+      .accounts({ owner: player.publicKey })
+      .signers([player])
+      .rpc();
+
+    // The session key signs in place of the owner
+    await program.methods
+      .playGameBalance(betParams(session_key_game_session_id))
+      .accounts({
+        owner: player.publicKey,
+        operator: operationAdmin.publicKey,
+        sessionSigner: sessionKey.publicKey,
+        sessionToken: sessionTokenPDA,
+      })
+      .signers([sessionKey, operationAdmin])
+      .rpc();
+
+    const token = await program.account.sessionToken.fetch(sessionTokenPDA);
+    expect(token.spent.eq(betAmount)).to.be.true;
+
+    // The next bet would go past max_spend
+    const overId = session_key_game_session_id.addn(100);
+    try {
+      await program.methods
+        .playGameBalance(betParams(overId))
+        .accounts({
+          owner: player.publicKey,
+          operator: operationAdmin.publicKey,
+          sessionSigner: sessionKey.publicKey,
+          sessionToken: sessionTokenPDA,
+        })
+        .signers([sessionKey, operationAdmin])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("SessionLimitExceeded");
+    }
+
+    const sessionPool = await program.account.playerPool.fetch(
+      sessionPlayerPoolPDA(session_key_game_session_id)
+    );
+    await slotHashAfter(sessionPool.betSlot);
+    await program.methods
+      .setResult({ gameSessionId: session_key_game_session_id, serverSeed: [...sessionServerSeed] })
+      .accounts({
+        owner: player.publicKey,
+        operator: operationAdmin.publicKey,
+        gameVault: sessionGameVaultPDA,
+        playerPool: sessionPlayerPoolPDA(session_key_game_session_id),
+        playerBalance: playerBalancePDA,
+      })
+      .signers([operationAdmin])
+      .rpc();
+
+    await program.methods
+      .revokeSessionToken()
+      .accounts({ owner: player.publicKey, sessionToken: sessionTokenPDA })
+      .signers([player])
+      .rpc();
+    expect(await connection.getAccountInfo(sessionTokenPDA)).to.be.null;
+  });
+
+  it("should withdraw funds only with threshold treasury approvals", async () => {
+    const recipient = Keypair.generate();
+    const amount = new anchor.BN(0.5 * LAMPORTS_PER_SOL);
+
+    await program.methods
+      .setTreasuryConfig([financialAdmin.publicKey, updateAdmin.publicKey], 2, new anchor.BN(3600))
+      .accounts({ admin: superAdmin.publicKey })
+      .signers([superAdmin])
+      .rpc();
+
+    const [treasuryConfigPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("treasury-config")],
+      program.programId
+    );
+    const treasuryConfig = await program.account.treasuryConfig.fetch(treasuryConfigPDA);
+    const [proposalPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("withdrawal-proposal"), treasuryConfig.nextProposalId.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+
+    // Other treasury signers approve, only the finance authority proposes
+    try {
+      await program.methods
+        .proposeWithdrawal(recipient.publicKey, PublicKey.default, amount)
+        .accounts({ proposer: updateAdmin.publicKey, proposal: proposalPDA })
+        .signers([updateAdmin])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("UnauthorizedFinanceAdmin");
+    }
+
+    await program.methods
+      .proposeWithdrawal(recipient.publicKey, PublicKey.default, amount)
+      .accounts({ proposer: financialAdmin.publicKey, proposal: proposalPDA })
+      .signers([financialAdmin])
+      .rpc();
+
+    // One of two approvals is not enough
+    try {
+      await program.methods
+        .executeWithdrawal()
+        .accounts({
+          executor: operationAdmin.publicKey,
+          proposer: financialAdmin.publicKey,
+          recipient: recipient.publicKey,
+          proposal: proposalPDA,
+        })
+        .signers([operationAdmin])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("ThresholdNotMet");
+    }
+
+    // Only members of the signer set can approve
+    try {
+      await program.methods
+        .approveWithdrawal()
+        .accounts({ signer: operationAdmin.publicKey, proposal: proposalPDA })
+        .signers([operationAdmin])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("NotTreasurySigner");
+    }
+
+    await program.methods
+      .approveWithdrawal()
+      .accounts({ signer: updateAdmin.publicKey, proposal: proposalPDA })
+      .signers([updateAdmin])
+      .rpc();
+
+    const execute = () =>
+      program.methods
+        .executeWithdrawal()
+        .accounts({
+          executor: operationAdmin.publicKey,
+          proposer: financialAdmin.publicKey,
+          recipient: recipient.publicKey,
+          proposal: proposalPDA,
+        })
+        .signers([operationAdmin])
+        .rpc();
+
+    // The recipient is not on the allowlist yet
+    try {
+      await execute();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("AccountNotInitialized");
+    }
+
+    await program.methods
+      .addAllowedRecipient(recipient.publicKey)
+      .accounts({ admin: superAdmin.publicKey })
+      .signers([superAdmin])
+      .rpc();
+
+    // Nor may it go past the epoch cap
+    const setWithdrawalCap = async (cap: anchor.BN) => {
+      await program.methods
+        .setWithdrawalEpochCap(cap)
+        .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+        .signers([updateAdmin])
+        .rpc();
+      await applyConfig({ withdrawalEpochCap: {} });
+    };
+    await setWithdrawalCap(amount.subn(1));
+    try {
+      await execute();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("WithdrawalCapExceeded");
+    }
+    await setWithdrawalCap(new anchor.BN(100 * LAMPORTS_PER_SOL));
+
+    // Nor below the reserve floor
+    const setMinReserve = async (reserve: anchor.BN) => {
+      await program.methods
+        .setMinReserve(reserve)
+        .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+        .signers([updateAdmin])
+        .rpc();
+      await applyConfig({ minReserve: {} });
+    };
+    await setMinReserve(new anchor.BN(await connection.getBalance(casinoVaultPDA)));
+    try {
+      await execute();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("ReserveFloorViolation");
+    }
+    await setMinReserve(new anchor.BN(0));
+
+    const sig = await program.methods
+      .executeWithdrawal()
+      .accounts({
+        executor: operationAdmin.publicKey,
+        proposer: financialAdmin.publicKey,
+        recipient: recipient.publicKey,
+        proposal: proposalPDA,
+      })
+      .signers([operationAdmin])
+      .rpc();
+    console.log(`Withdraw Sig => https://solscan.io/${sig}`);
+
+    const recipientBalance = await connection.getBalance(recipient.publicKey);
+    expect(recipientBalance).to.equal(amount.toNumber());
+    expect(await connection.getAccountInfo(proposalPDA)).to.be.null;
+  });
+
+  it("should withdraw tokens from the casino token vault through the treasury", async () => {
+    const recipient = Keypair.generate();
+    const mint = await createMint(connection, superAdmin, superAdmin.publicKey, null, 6);
+    const [casinoTokenVaultPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault-authority"), mint.toBuffer()],
+      program.programId
+    );
+
+    await program.methods
+      .addToken(new anchor.BN(1_000_000), new anchor.BN(100_000_000))
+      .accounts({ admin: updateAdmin.publicKey, tokenMint: mint })
+      .signers([updateAdmin])
+      .rpc();
+
+    const adminAta = await getOrCreateAssociatedTokenAccount(connection, superAdmin, mint, superAdmin.publicKey);
+    await mintTo(connection, superAdmin, mint, adminAta.address, superAdmin, 100_000_000);
+    await transfer(connection, superAdmin, adminAta.address, casinoTokenVaultPDA, superAdmin, 100_000_000);
+
+    const recipientAta = await getOrCreateAssociatedTokenAccount(connection, superAdmin, mint, recipient.publicKey);
+    await program.methods
+      .addAllowedRecipient(recipient.publicKey)
+      .accounts({ admin: superAdmin.publicKey })
+      .signers([superAdmin])
+      .rpc();
+
+    const [treasuryConfigPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("treasury-config")],
+      program.programId
+    );
+    const proposeApproved = async (amount: anchor.BN) => {
+      const treasuryConfig = await program.account.treasuryConfig.fetch(treasuryConfigPDA);
+      const [proposalPDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("withdrawal-proposal"), treasuryConfig.nextProposalId.toArrayLike(Buffer, "be", 8)],
+        program.programId
+      );
+      await program.methods
+        .proposeWithdrawal(recipient.publicKey, mint, amount)
+        .accounts({ proposer: financialAdmin.publicKey, proposal: proposalPDA })
+        .signers([financialAdmin])
+        .rpc();
+      await program.methods
+        .approveWithdrawal()
+        .accounts({ signer: updateAdmin.publicKey, proposal: proposalPDA })
+        .signers([updateAdmin])
+        .rpc();
+      return proposalPDA;
+    };
+    const executeToken = (proposalPDA: PublicKey) =>
+      program.methods
+        .executeTokenWithdrawal()
+        .accounts({
+          executor: operationAdmin.publicKey,
+          proposer: financialAdmin.publicKey,
+          recipient: recipient.publicKey,
+          recipientTokenAccount: recipientAta.address,
+          proposal: proposalPDA,
+          tokenMint: mint,
+        })
+        .signers([operationAdmin])
+        .rpc();
+
+    // More than the vault holds
+    const tooMuch = await proposeApproved(new anchor.BN(100_000_001));
+    try {
+      await executeToken(tooMuch);
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("InsufficientCasinoVault");
+    }
+
+    const amount = new anchor.BN(40_000_000);
+    const proposalPDA = await proposeApproved(amount);
+
+    // A token proposal cannot be paid out in SOL
+    try {
+      await program.methods
+        .executeWithdrawal()
+        .accounts({
+          executor: operationAdmin.publicKey,
+          proposer: financialAdmin.publicKey,
+          recipient: recipient.publicKey,
+          proposal: proposalPDA,
+        })
+        .signers([operationAdmin])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("InvalidTokenMint");
+    }
+
+    await executeToken(proposalPDA);
+
+    expect((await getAccount(connection, recipientAta.address)).amount.toString()).to.equal(amount.toString());
+    expect((await getAccount(connection, casinoTokenVaultPDA)).amount.toString()).to.equal("60000000");
+    expect(await connection.getAccountInfo(proposalPDA)).to.be.null;
+  });
+
+  it("should issue LP shares and pay queued exits at the next epoch boundary", async () => {
+    const lpProvider = Keypair.generate();
+    await sendAndConfirmTransaction(
+      connection,
+      new Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: superAdmin.publicKey,
+          toPubkey: lpProvider.publicKey,
+          lamports: 2 * LAMPORTS_PER_SOL,
+        })
+      ),
+      [superAdmin]
+    );
+
+    const [lpMintPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("lp-mint")],
+      program.programId
+    );
+    const [houseSharesPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("house-shares")],
+      program.programId
+    );
+
+    await program.methods
+      .initLiquidityPool()
+      .accounts({ admin: superAdmin.publicKey })
+      .signers([superAdmin])
+      .rpc();
+
+    // The house bankroll above the min reserve is issued shares 1:1
+    const globalAuthority = await program.account.globalPool.fetch(globalAuthorityPDA);
+    const equity = (await connection.getBalance(casinoVaultPDA)) - globalAuthority.minReserve.toNumber();
+    expect(Number((await getAccount(connection, houseSharesPDA)).amount)).to.equal(equity);
+
+    const providerShares = await getOrCreateAssociatedTokenAccount(
+      connection,
+      lpProvider,
+      lpMintPDA,
+      lpProvider.publicKey
+    );
+
+    const amount = new anchor.BN(LAMPORTS_PER_SOL);
+
+    // Deposits can be paused on their own
+    const PAUSE_LIQUIDITY = 8;
+    await program.methods
+      .setPaused(PAUSE_LIQUIDITY)
+      .accounts({ admin: superAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([superAdmin])
+      .rpc();
+    try {
+      await program.methods
+        .depositLiquidity(amount)
+        .accounts({ provider: lpProvider.publicKey, providerShares: providerShares.address })
+        .signers([lpProvider])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("Paused");
+    }
+    await program.methods
+      .setPaused(0)
+      .accounts({ admin: superAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([superAdmin])
+      .rpc();
+
+    await program.methods
+      .depositLiquidity(amount)
+      .accounts({ provider: lpProvider.publicKey, providerShares: providerShares.address })
+      .signers([lpProvider])
+      .rpc();
+
+    const shares = (await getAccount(connection, providerShares.address)).amount;
+    expect(Number(shares)).to.equal(amount.toNumber());
+
+    // Exits wait for the next LP epoch boundary
+    await program.methods
+      .setLpEpochDuration(new anchor.BN(2))
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    await applyConfig({ lpEpochDuration: {} });
+
+    // Every request is its own ticket account paid for by the provider, so exits share no capacity
+    const ticketPDA = (nonce: number) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("lp-withdrawal"), lpProvider.publicKey.toBuffer(), new anchor.BN(nonce).toArrayLike(Buffer, "be", 8)],
+        program.programId
+      )[0];
+    const requestWithdrawal = (nonce: number, shareAmount: number) =>
+      program.methods
+        .requestLiquidityWithdrawal(new anchor.BN(nonce), new anchor.BN(shareAmount))
+        .accounts({ provider: lpProvider.publicKey, providerShares: providerShares.address })
+        .signers([lpProvider])
+        .rpc();
+
+    const firstShares = Number(shares) / 2;
+    const secondShares = Number(shares) - firstShares;
+    await requestWithdrawal(0, firstShares);
+    await requestWithdrawal(1, secondShares);
+    expect(Number((await getAccount(connection, providerShares.address)).amount)).to.equal(0);
+
+    // A nonce can't be reused while its ticket is open
+    try {
+      await requestWithdrawal(0, 1);
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.match(/already in use|custom program error: 0x0/);
+    }
+
+    const ticket = await program.account.lpWithdrawalTicket.fetch(ticketPDA(0));
+    expect(ticket.shares.toNumber()).to.equal(firstShares);
+
+    const crank = (nonce: number) =>
+      program.methods
+        .processLiquidityWithdrawal()
+        .accounts({
+          cranker: operationAdmin.publicKey,
+          provider: lpProvider.publicKey,
+          ticket: ticketPDA(nonce),
+        })
+        .signers([operationAdmin])
+        .rpc({ commitment: "confirmed" });
+
+    const unlocksAt = ticket.unlocksAt.toNumber();
+    if (Math.floor(Date.now() / 1000) < unlocksAt - 1) {
+      try {
+        await crank(0);
+        expect.fail("Should have thrown error");
+      } catch (err) {
+        expect(err.toString()).to.contain("LpWithdrawalNotDue");
+      }
+    }
+    await sleep(3000);
+
+    // A due exit still waits while the vault has nothing above its reserve floor
+    const setMinReserve = async (minReserve: number) => {
+      await program.methods
+        .setMinReserve(new anchor.BN(minReserve))
+        .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+        .signers([updateAdmin])
+        .rpc();
+      await applyConfig({ minReserve: {} });
+    };
+    await setMinReserve(await connection.getBalance(casinoVaultPDA));
+    try {
+      await crank(0);
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("PoolInsolvent");
+    }
+    expect(await connection.getAccountInfo(ticketPDA(0))).to.not.be.null;
+    await setMinReserve(globalAuthority.minReserve.toNumber());
+
+    // Each ticket is paid on its own and its rent goes back to the provider
+    for (const [nonce, ticketShares] of [[0, firstShares], [1, secondShares]]) {
+      const rent = await connection.getBalance(ticketPDA(nonce));
+      const providerBalanceBefore = await connection.getBalance(lpProvider.publicKey);
+      const sig = await crank(nonce);
+
+      const withdrawn = (await getEvents(sig)).find((e) => e.name === "liquidityWithdrawn");
+      expect(withdrawn.data.nonce.toNumber()).to.equal(nonce);
+      expect(withdrawn.data.amount.toNumber()).to.equal(ticketShares);
+      expect(await connection.getBalance(lpProvider.publicKey)).to.equal(
+        providerBalanceBefore + ticketShares + rent
+      );
+      expect(await connection.getAccountInfo(ticketPDA(nonce))).to.be.null;
+    }
+
+    await program.methods
+      .setLpEpochDuration(new anchor.BN(24 * 60 * 60))
+      .accounts({ admin: updateAdmin.publicKey, globalPool: globalAuthorityPDA })
+      .signers([updateAdmin])
+      .rpc();
+    await applyConfig({ lpEpochDuration: {} });
+  });
+
+  it("should not dilute LP holders with a deposit made while a bet is open", async () => {
+    const lpProvider = Keypair.generate();
+    await sendAndConfirmTransaction(
+      connection,
+      new Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: superAdmin.publicKey,
+          toPubkey: lpProvider.publicKey,
+          lamports: 2 * LAMPORTS_PER_SOL,
+        })
+      ),
+      [superAdmin]
+    );
+    const [lpMintPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("lp-mint")],
+      program.programId
+    );
+    const providerShares = await getOrCreateAssociatedTokenAccount(
+      connection,
+      lpProvider,
+      lpMintPDA,
+      lpProvider.publicKey
+    );
+
+    const [lpPlayerPoolPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("player-pool"), lp_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const [lpGameVaultPDA] = PublicKey.findProgramAddressSync(
+      [player.publicKey.toBuffer(), Buffer.from("vault-authority"), lp_game_session_id.toArrayLike(Buffer, "be", 8)],
+      program.programId
+    );
+    const lpServerSeed = randomBytes(32);
+    await program.methods
+      .playGame({
+        gameKind: { overUnder: {} },
+        targetNumber: 57,
+        rangeEnd: 0,
+        isUnder: true,
+        betAmount: new anchor.BN(0.2 * LAMPORTS_PER_SOL),
+        gameSessionId: lp_game_session_id,
+        serverSeedHash: sha256(lpServerSeed),
+        clientSeed: [...clientSeed],
+        slotHash: false,
+      })
+      .accounts({
+        owner: player.publicKey,
+        operator: operationAdmin.publicKey,
+        playerPool: lpPlayerPoolPDA,
+        gameVault: lpGameVaultPDA,
+      })
+      .signers([player, operationAdmin])
+      .rpc();
+
+    const globalAuthority = await program.account.globalPool.fetch(globalAuthorityPDA);
+    expect(globalAuthority.outstandingLiability.toNumber()).to.be.greaterThan(0);
+    const minReserve = BigInt(globalAuthority.minReserve.toString());
+    const vaultBefore = BigInt(await connection.getBalance(casinoVaultPDA));
+    const supplyBefore = (await getMint(connection, lpMintPDA)).supply;
+
+    // Shares are priced on the vault above the min reserve, without discounting the open bet
+    const amount = BigInt(LAMPORTS_PER_SOL);
+    await program.methods
+      .depositLiquidity(new anchor.BN(amount.toString()))
+      .accounts({ provider: lpProvider.publicKey, providerShares: providerShares.address })
+      .signers([lpProvider])
+      .rpc();
+    const minted = (await getAccount(connection, providerShares.address)).amount;
+    expect(minted).to.equal((amount * supplyBefore) / (vaultBefore - minReserve));
+
+    const lpPool = await program.account.playerPool.fetch(lpPlayerPoolPDA);
+    await slotHashAfter(lpPool.betSlot);
+    await program.methods
+      .setResult({ gameSessionId: lp_game_session_id, serverSeed: [...lpServerSeed] })
+      .accounts({
+        owner: player.publicKey,
+        operator: operationAdmin.publicKey,
+        gameVault: lpGameVaultPDA,
+        playerPool: lpPlayerPoolPDA,
+      })
+      .signers([operationAdmin])
+      .rpc();
+
+    // Earlier holders keep their equity from before the deposit plus their pro rata share of the
+    // bet's result, whichever way it went
+    const vaultAfter = BigInt(await connection.getBalance(casinoVaultPDA));
+    const supplyAfter = (await getMint(connection, lpMintPDA)).supply;
+    const betResult = vaultAfter - vaultBefore - amount;
+    const earlierHoldersValue = (vaultAfter - minReserve) * supplyBefore;
+    expect(earlierHoldersValue >= (vaultBefore - minReserve) * supplyAfter + betResult * supplyBefore).to.be.true;
+  });
+
+  it("should allow updating RTP", async () => {
+    const newRtp = new anchor.BN(90);
+    const tx = await program.methods
+      .setRtp(newRtp)
+      .accounts({
+        admin: updateAdmin.publicKey,
+        globalPool: globalAuthorityPDA,
+      })
+      .transaction();
+
+    tx.feePayer = updateAdmin.publicKey;
+    tx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
+    console.log(await connection.simulateTransaction(tx));
+    const sig = await sendAndConfirmTransaction(connection, tx, [updateAdmin]);
+    console.log(`RTP update Sig => https://solscan.io/${sig}`);
+
+    const globalAuthority = await program.account.globalPool.fetch(
+      globalAuthorityPDA
+    );
+    expect(newRtp.eq(globalAuthority.rtp)).to.be.true;
+  });
+  
+  it("should allow updating MinNum", async () => {
+    const newMinNum = 20;
+    const tx = await program.methods
+      .setMinNum(newMinNum)
+      .accounts({
+        admin: updateAdmin.publicKey,
+        globalPool: globalAuthorityPDA,
+      })
+      .transaction();
+
+    tx.feePayer = updateAdmin.publicKey;
+    tx.recentBlockhash = (await connection.getLatestBlockhash()).blockhash;
+    console.log(await connection.simulateTransaction(tx));
+    const sig = await sendAndConfirmTransaction(connection, tx, [updateAdmin]);
+    console.log(`MinTargetNumber update Sig => https://solscan.io/${sig}`);
+    await applyConfig({ minNum: {} });
+
+    const globalAuthority = await program.account.globalPool.fetch(
+      globalAuthorityPDA
+    );
+    expect(newMinNum === globalAuthority.minNum).to.be.true;
+  });
+
+  it("should transfer authorities only after the new key accepts", async () => {
+    await program.methods
+      .proposeOperationAuthority(newAdmin.publicKey)
+      .accounts({
+        admin: superAdmin.publicKey,
+        globalPool: globalAuthorityPDA,
+      })
+      .signers([superAdmin])
+      .rpc();
+
+    let globalAuthority = await program.account.globalPool.fetch(
+      globalAuthorityPDA
+    );
+    expect(globalAuthority.operationAuthority.toString()).to.equal(
+      operationAdmin.publicKey.toString()
+    );
+    expect(globalAuthority.pendingOperationAuthority.toString()).to.equal(
+      newAdmin.publicKey.toString()
+    );
+
+    try {
+      await program.methods
+        .acceptOperationAuthority()
+        .accounts({ newAuthority: secondPlayer.publicKey })
+        .signers([secondPlayer])
+        .rpc();
+      expect.fail("Should have thrown error");
+    } catch (err) {
+      expect(err.toString()).to.contain("NotPendingAuthority");
+    }
+
+    await program.methods
+      .acceptOperationAuthority()
+      .accounts({ newAuthority: newAdmin.publicKey })
+      .signers([newAdmin])
+      .rpc();
+
+    globalAuthority = await program.account.globalPool.fetch(
+      globalAuthorityPDA
+    );
+    expect(globalAuthority.operationAuthority.toString()).to.equal(
+      newAdmin.publicKey.toString()
+    );
+    expect(globalAuthority.pendingOperationAuthority.toString()).to.equal(
+      PublicKey.default.toString()
+    );
+  });
+});
+
+// Crank a queued config change, retrying until its timelock has passed
+async function applyConfig(field: object) {
+  await afterTimelock(() =>
+    program.methods
+      .applyPendingConfig(field as any)
+      .accounts({ globalPool: globalAuthorityPDA })
+      .rpc()
+  );
+}
+
+async function applyRandomnessMode() {
+  await afterTimelock(() =>
+    program.methods
+      .applyRandomnessMode()
+      .accounts({ globalPool: globalAuthorityPDA })
+      .rpc()
+  );
+}
+
+// The suite initializes the program with a timelock of CONFIG_TIMELOCK seconds
+async function afterTimelock(crank: () => Promise<unknown>) {
+  for (;;) {
+    try {
+      await crank();
+      return;
+    } catch (err) {
+      if (!err.toString().includes("ConfigTimelocked")) {
+        throw err;
+      }
+      await sleep(1000);
+    }
+  }
+}
+
+async function getEvents(sig: string) {
+  const tx = await connection.getTransaction(sig, {
+    commitment: "confirmed",
+    maxSupportedTransactionVersion: 0,
+  });
+  const parser = new anchor.EventParser(program.programId, program.coder);
+  return [...parser.parseLogs(tx.meta.logMessages)];
+}
+
+// Mirrors utils::compute_roll in the program
+function computeRoll(
+  serverSeed: Buffer,
+  clientSeed: Buffer,
+  gameSessionId: anchor.BN,
+  index = 0
+): number {
+  const hasher = createHash("sha256")
+    .update(serverSeed)
+    .update(clientSeed)
+    .update(gameSessionId.toArrayLike(Buffer, "be", 8));
+  if (index > 0) {
+    hasher.update(Buffer.from([index]));
+  }
+  const hash = hasher.digest();
+  return Number(hash.readBigUInt64LE(0) % BigInt(100));
+}
+
+// Mirrors utils::mix_slot_hash in the program
+function mixSlotHash(serverSeed: Buffer, slotHash: Buffer): Buffer {
+  return createHash("sha256").update(serverSeed).update(slotHash).digest();
+}
+
+// Mirrors utils::find_slot_hash, waiting until the first slot after betSlot is in the sysvar
+async function slotHashAfter(betSlot: anchor.BN): Promise<Buffer> {
+  for (;;) {
+    const { data } = await connection.getAccountInfo(SYSVAR_SLOT_HASHES_PUBKEY, "confirmed");
+    const len = Number(data.readBigUInt64LE(0));
+    let next: Buffer = null;
+    for (let i = 0; i < len; i++) {
+      const entry = 8 + i * 40;
+      if (data.readBigUInt64LE(entry) <= BigInt(betSlot.toString())) {
+        if (next) {
+          return next;
+        }
+        break;
+      }
+      next = data.subarray(entry + 8, entry + 40);
+    }
+    await sleep(400);
+  }
+}
+
+function sha256(data: Buffer): number[] {
+  return [...createHash("sha256").update(data).digest()];
+}
 
 function sleep(ms) {
   return new Promise((resolve) => setTimeout(resolve, ms));